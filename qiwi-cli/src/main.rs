@@ -1,15 +1,83 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
 use clap::*;
 use phonenumber::PhoneNumber;
 use qiwi::*;
 use serde::*;
-use std::path::*;
+use std::{fmt, io::Write, path::*};
 use tokio_stream::*;
 use tracing_subscriber::{prelude::*, EnvFilter};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 struct Config {
     phone: String,
-    token: String,
+    /// Plaintext token saved by older versions, moved to the keyring on login.
+    ///
+    /// Kept if the keyring is unavailable, so that it is not lost.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("phone", &self.phone)
+            .field("token", &self.token.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+const KEYRING_SERVICE: &str = "qiwi-cli";
+
+fn keyring_entry(phone: &str) -> keyring::Result<keyring::Entry> {
+    keyring::Entry::new(KEYRING_SERVICE, phone)
+}
+
+/// Returns whether the token was saved.
+///
+/// Keyring is often unavailable on headless machines, so with `--token-stdin` its errors are not fatal.
+fn save_token(
+    phone: &str,
+    token: &str,
+    token_stdin: bool,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    println!("Saving token to the OS keyring");
+    match keyring_entry(phone).and_then(|entry| entry.set_password(token)) {
+        Ok(()) => Ok(true),
+        Err(e) if token_stdin => {
+            eprintln!(
+                "Failed to save token to the OS keyring ({e}), pass it in QIWI_TOKEN instead"
+            );
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// `QIWI_TOKEN` takes precedence over the keyring.
+fn load_token(config: &Config) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    if let Ok(token) = std::env::var("QIWI_TOKEN") {
+        return Ok(token);
+    }
+
+    match keyring_entry(&config.phone).and_then(|entry| entry.get_password()) {
+        Ok(token) => Ok(token),
+        Err(e) => match &config.token {
+            Some(token) => {
+                eprintln!("Token is stored in plaintext, run `login` to move it to the OS keyring");
+                Ok(token.clone())
+            }
+            None => Err(format!(
+                "failed to read token from keyring ({e}) and QIWI_TOKEN is not set"
+            )
+            .into()),
+        },
+    }
+}
+
+async fn read_config() -> Option<Config> {
+    let data = tokio::fs::read(config_location()).await.ok()?;
+    toml::from_str::<Config>(&String::from_utf8(data).ok()?).ok()
 }
 
 fn config_location() -> PathBuf {
@@ -22,14 +90,33 @@ fn config_location() -> PathBuf {
 #[derive(Debug, Parser)]
 enum UnauthorizedCmd {
     /// Authorize client
-    Login,
+    Login {
+        #[arg(long)]
+        phone: Option<PhoneNumber>,
+        /// Read token from stdin without prompting, keep going if the OS keyring is unavailable
+        #[arg(long)]
+        token_stdin: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ExportFormat {
+    Csv,
+    /// One JSON object per line
+    Json,
 }
 
 #[derive(Debug, Parser)]
 #[allow(clippy::large_enum_variant)]
 enum AuthorizedCmd {
     /// Reauthorize client
-    Login,
+    Login {
+        #[arg(long)]
+        phone: Option<PhoneNumber>,
+        /// Read token from stdin without prompting, keep going if the OS keyring is unavailable
+        #[arg(long)]
+        token_stdin: bool,
+    },
     /// Get profile info,
     ProfileInfo,
     /// Get payment history,
@@ -37,38 +124,129 @@ enum AuthorizedCmd {
     CommissionInfo {
         provider: ProviderId,
     },
+    /// Export payment history
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        /// Output file, stdout if not specified
+        #[arg(long)]
+        output: Option<PathBuf>,
+        #[arg(long)]
+        from: Option<DateTime<Utc>>,
+        #[arg(long)]
+        to: Option<DateTime<Utc>>,
+    },
+    /// Get commission for a transfer
+    Quote {
+        provider: ProviderId,
+        to: PhoneNumber,
+        #[arg(value_parser = parse_amount)]
+        amount: BigDecimal,
+    },
+    /// Transfer funds to another QIWI wallet
+    Transfer {
+        to: PhoneNumber,
+        #[arg(value_parser = parse_amount)]
+        amount: BigDecimal,
+        #[arg(long, default_value = "")]
+        comment: Comment,
+        /// Do not ask for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
 }
 
-async fn do_authorize() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let mut stdin = tokio_util::codec::FramedRead::new(
-        tokio::io::stdin(),
-        tokio_util::codec::LinesCodec::new(),
-    );
+/// Shared by all prompts, lines buffered by one reader would be lost to the others.
+type Stdin = tokio_util::codec::FramedRead<tokio::io::Stdin, tokio_util::codec::LinesCodec>;
+
+async fn confirm(
+    stdin: &mut Stdin,
+    prompt: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    println!("{prompt} [y/N]");
 
-    println!("Please enter user ID");
+    Ok(matches!(
+        stdin.next().await.transpose()?.as_deref().map(str::trim),
+        Some("y" | "Y" | "yes")
+    ))
+}
+
+async fn prompt(
+    stdin: &mut Stdin,
+    prompt: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    println!("{prompt}");
 
-    let phone = stdin
+    Ok(stdin
         .next()
         .await
         .unwrap_or_else(|| std::process::exit(0))?
-        .parse::<PhoneNumber>()?
-        .to_string();
+        .trim()
+        .to_string())
+}
 
-    println!("Please enter your token");
+async fn do_authorize(
+    stdin: &mut Stdin,
+    phone: Option<PhoneNumber>,
+    token_stdin: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let phone = match phone {
+        Some(phone) => phone,
+        None => {
+            println!("Please enter user ID");
 
-    let token = stdin
-        .next()
-        .await
-        .unwrap_or_else(|| std::process::exit(0))?;
+            stdin
+                .next()
+                .await
+                .unwrap_or_else(|| std::process::exit(0))?
+                .parse::<PhoneNumber>()?
+        }
+    }
+    .to_string();
+
+    // Token of another wallet would be orphaned in the keyring, it is dropped with the old config
+    let mut old_token = match read_config().await {
+        Some(Config {
+            phone: old_phone,
+            token: Some(old_token),
+        }) if old_phone == phone => Some(old_token),
+        _ => None,
+    };
+    if let Some(token) = &old_token {
+        println!("Moving plaintext token out of the config");
+        if save_token(&phone, token, token_stdin)? {
+            old_token = None;
+        }
+    }
+
+    // Token in environment is used as is, without touching the keyring
+    if std::env::var_os("QIWI_TOKEN").is_none() {
+        if !token_stdin {
+            println!("Please enter your token");
+        }
+
+        let token = stdin
+            .next()
+            .await
+            .unwrap_or_else(|| std::process::exit(0))?;
+
+        if save_token(&phone, &token, token_stdin)? {
+            old_token = None;
+        }
+    }
 
     let path = config_location();
-    println!("Saving token on disk to {}", path.to_string_lossy());
+    println!("Saving config on disk to {}", path.to_string_lossy());
     if let Some(parent) = path.parent() {
         let _ = std::fs::create_dir_all(parent);
     }
     tokio::fs::write(
         path,
-        toml::to_string(&Config { phone, token })?.into_bytes(),
+        toml::to_string(&Config {
+            phone,
+            token: old_token,
+        })?
+        .into_bytes(),
     )
     .await?;
 
@@ -83,42 +261,163 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .with(filter)
         .init();
 
+    let mut stdin = tokio_util::codec::FramedRead::new(
+        tokio::io::stdin(),
+        tokio_util::codec::LinesCodec::new(),
+    );
+
     let config = async move {
-        if let Ok(data) = tokio::fs::read(config_location()).await {
-            if let Ok(config) = toml::from_str::<Config>(&String::from_utf8(data).unwrap()) {
-                return Some(config);
-            }
+        if let Ok(phone) = std::env::var("QIWI_PHONE") {
+            return Some(Config { phone, token: None });
         }
 
-        None
+        read_config().await
     }
     .await;
 
     match config {
         None => match UnauthorizedCmd::parse() {
-            UnauthorizedCmd::Login => do_authorize().await?,
+            UnauthorizedCmd::Login { phone, token_stdin } => {
+                do_authorize(&mut stdin, phone, token_stdin).await?
+            }
         },
         Some(config) => match AuthorizedCmd::parse() {
-            AuthorizedCmd::Login => do_authorize().await?,
+            AuthorizedCmd::Login { phone, token_stdin } => {
+                do_authorize(&mut stdin, phone, token_stdin).await?
+            }
             other => {
                 println!("Using config {config:?}");
-                let client = Client::new(config.phone.parse()?, config.token);
-                let _ = client;
+                let token = load_token(&config)?;
+                let client = Client::new(config.phone.parse()?, token)?;
                 match other {
                     AuthorizedCmd::ProfileInfo => {
                         let profile_info = client.profile_info().await?;
                         println!("Profile info:");
-                        println!("{profile_info:?}");
+                        println!("{profile_info}");
                     }
                     AuthorizedCmd::PaymentHistory => {
-                        while let Some(entry) = client.payment_history().next().await.transpose()? {
-                            println!("{entry:?}");
+                        let mut history = client.payment_history();
+                        while let Some(entry) = history.next().await.transpose()? {
+                            println!("{entry}");
                         }
                     }
                     AuthorizedCmd::CommissionInfo { provider } => {
                         println!("{:?}", client.commission_info(provider).await?)
                     }
-                    other => unimplemented!("{other:?}"),
+                    AuthorizedCmd::Export {
+                        format,
+                        output,
+                        from,
+                        to,
+                    } => {
+                        let out: Box<dyn Write> = match output {
+                            Some(path) => Box::new(std::fs::File::create(path)?),
+                            None => Box::new(std::io::stdout()),
+                        };
+                        let mut history = client.payment_history_filtered(HistoryQuery {
+                            start_date: from,
+                            end_date: to,
+                            ..Default::default()
+                        });
+                        match format {
+                            ExportFormat::Csv => {
+                                let mut writer = csv::Writer::from_writer(out);
+                                writer.write_record([
+                                    "date",
+                                    "type",
+                                    "status",
+                                    "amount",
+                                    "currency",
+                                    "commission",
+                                    "account",
+                                    "provider",
+                                    "comment",
+                                ])?;
+                                while let Some(entry) = history.next().await.transpose()? {
+                                    writer.write_record([
+                                        entry.date.to_rfc3339(),
+                                        entry.payment_type.as_str().to_string(),
+                                        entry.status.as_str().to_string(),
+                                        entry.sum.amount.to_string(),
+                                        entry.sum.currency.to_string(),
+                                        entry.commission.amount.to_string(),
+                                        entry.account,
+                                        entry.provider.short_name,
+                                        entry.comment,
+                                    ])?;
+                                }
+                                writer.flush()?;
+                            }
+                            ExportFormat::Json => {
+                                let mut out = std::io::BufWriter::new(out);
+                                while let Some(entry) = history.next().await.transpose()? {
+                                    serde_json::to_writer(&mut out, &entry)?;
+                                    writeln!(out)?;
+                                }
+                                out.flush()?;
+                            }
+                        }
+                    }
+                    AuthorizedCmd::Quote {
+                        provider,
+                        to,
+                        amount,
+                    } => {
+                        println!(
+                            "{}",
+                            client
+                                .commission_quote(provider, to, amount, client.region().currency())
+                                .await?
+                        )
+                    }
+                    AuthorizedCmd::Transfer {
+                        to,
+                        amount,
+                        comment,
+                        yes,
+                    } => {
+                        let currency = client.region().currency();
+                        let commission = client
+                            .commission_quote(
+                                ProviderId::QIWI,
+                                to.clone(),
+                                amount.clone(),
+                                currency,
+                            )
+                            .await?;
+                        let commission = commission.amount;
+                        let total = &amount + &commission;
+                        let question = format!(
+                            "Send {amount} {currency:?} to {to}? Commission {commission} {currency:?}, total {total} {currency:?}."
+                        );
+                        if !yes && !confirm(&mut stdin, &question).await? {
+                            println!("Aborted");
+                            return Ok(());
+                        }
+
+                        let data = client
+                            .transfer(
+                                None,
+                                amount,
+                                currency,
+                                TransferDirection::Qiwi {
+                                    to_phone: to,
+                                    to_currency: currency,
+                                },
+                                comment,
+                            )
+                            .await?;
+                        match data {
+                            TransferOutcome::Completed(data) => println!("{data}"),
+                            TransferOutcome::ConfirmationRequired { id, txn_id, .. } => {
+                                let code =
+                                    prompt(&mut stdin, "Please enter confirmation code from SMS")
+                                        .await?;
+                                println!("{}", client.confirm_transfer(id, &txn_id, &code).await?);
+                            }
+                        }
+                    }
+                    AuthorizedCmd::Login { .. } => unreachable!(),
                 }
             }
         },
@@ -0,0 +1,992 @@
+use crate::{models::*, request::*, transport::*};
+use anyhow::format_err;
+use async_stream::try_stream;
+use bigdecimal::BigDecimal;
+#[cfg(feature = "tokio")]
+use futures_util::future::Either;
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use http::{HeaderMap, Method, StatusCode};
+use phonenumber::PhoneNumber;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+#[cfg(feature = "tokio")]
+use tokio_util::sync::CancellationToken;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub type BoxStream<T> = Pin<Box<dyn Stream<Item = T> + Send>>;
+#[cfg(target_arch = "wasm32")]
+pub type BoxStream<T> = Pin<Box<dyn Stream<Item = T>>>;
+
+/// How far back history is searched for a payment retried with the same id.
+const PAYMENT_LOOKUP_DAYS: i64 = 7;
+
+/// Consecutive empty pages with a cursor after which payment history fails.
+const MAX_EMPTY_HISTORY_PAGES: u32 = 5;
+/// Delay before the first refetch after an empty page, doubled for each subsequent one.
+#[cfg(feature = "tokio")]
+const EMPTY_HISTORY_PAGE_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Length of the first date range oldest-first history is fetched in, each buffered in memory.
+const OLDEST_FIRST_WINDOW_DAYS: i64 = 1;
+/// Ranges are doubled after quiet ones up to this length, to save requests on sparse history.
+const OLDEST_FIRST_MAX_WINDOW_DAYS: i64 = 32;
+
+/// Stream of payment history entries, fetching pages lazily.
+///
+/// QIWI may lag and return an empty page that still points further. It is refetched with backoff,
+/// which needs the `tokio` feature; without it the stream fails on such a page instead.
+#[must_use = "streams do nothing unless polled"]
+pub struct PaymentHistory<T = PaymentHistoryEntry> {
+    inner: BoxStream<anyhow::Result<T>>,
+}
+
+impl<T> Stream for PaymentHistory<T> {
+    type Item = anyhow::Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Storage of payments keyed by payment id, used to make retries idempotent.
+pub trait PaymentStore: Debug + MaybeSendSync + 'static {
+    fn get(&self, id: u64) -> Option<TransferData>;
+    fn put(&self, id: u64, data: TransferData);
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryPaymentStore(Mutex<HashMap<u64, TransferData>>);
+
+impl PaymentStore for MemoryPaymentStore {
+    fn get(&self, id: u64) -> Option<TransferData> {
+        self.0.lock().unwrap().get(&id).cloned()
+    }
+
+    fn put(&self, id: u64, data: TransferData) {
+        self.0.lock().unwrap().insert(id, data);
+    }
+}
+
+/// Transfer prepared by [`Client::transfer_preview`] without sending it.
+#[derive(Clone, Debug)]
+pub struct TransferPreview {
+    /// Payment id the transfer would be made with.
+    pub id: u64,
+    /// Request that [`Client::transfer`] would send.
+    pub request: ApiRequest<TransferData>,
+    pub commission: Money,
+}
+
+/// Failure reported by [`Client::ping`].
+#[derive(Debug, thiserror::Error)]
+pub enum PingError {
+    #[error("token is invalid")]
+    TokenInvalid,
+    #[error("QIWI API is unavailable: {0:#}")]
+    Unavailable(anyhow::Error),
+}
+
+#[derive(Clone, Debug)]
+pub struct Client {
+    caller: CallerWrapper,
+    user: QiwiUser,
+    region: Region,
+    payment_store: Option<Arc<dyn PaymentStore>>,
+}
+
+impl Client {
+    /// Fails if `phone` is not a valid QIWI wallet number.
+    pub fn new(phone: PhoneNumber, token: impl Into<String>) -> anyhow::Result<Self> {
+        Self::with_region(phone, token, Region::default())
+    }
+
+    /// Same as [`Client::new`], but for wallet registered with a regional instance of QIWI.
+    pub fn with_region(
+        phone: PhoneNumber,
+        token: impl Into<String>,
+        region: Region,
+    ) -> anyhow::Result<Self> {
+        let mut client = Self::with_base_url(phone, token, region.base_url())?;
+        client.region = region;
+        Ok(client)
+    }
+
+    /// Same as [`Client::new`], but for API located at `addr`, e.g. a proxy or a mock server.
+    pub fn with_base_url(
+        phone: PhoneNumber,
+        token: impl Into<String>,
+        addr: impl Into<String>,
+    ) -> anyhow::Result<Self> {
+        Self::with_builder(phone, RemoteCaller::builder().addr(addr).bearer(token))
+    }
+
+    /// Same as [`Client::new`], but over transport configured with `builder`, e.g. with an observer.
+    pub fn with_builder(phone: PhoneNumber, builder: RemoteCallerBuilder) -> anyhow::Result<Self> {
+        let phone = QiwiUser::try_new(phone)?.0;
+        Ok(Self::with_transport(phone, builder.build()?))
+    }
+
+    /// Creates client over custom transport, e.g. `MiddlewareCaller`.
+    ///
+    /// Unlike [`Client::new`], `phone` is not validated, see [`QiwiUser::try_new`].
+    #[must_use]
+    pub fn with_transport(phone: PhoneNumber, transport: impl Transport) -> Self {
+        Self {
+            caller: CallerWrapper::new(transport),
+            user: QiwiUser(phone),
+            region: Region::default(),
+            payment_store: None,
+        }
+    }
+
+    /// Remember completed payments in `store`, so that repeating a payment with the same id
+    /// returns the stored result instead of calling QIWI again.
+    #[must_use]
+    pub fn with_payment_store(mut self, store: impl PaymentStore) -> Self {
+        self.payment_store = Some(Arc::new(store));
+        self
+    }
+
+    /// Limit the rate of requests, shared by all clones of this client.
+    #[cfg(feature = "rate-limit")]
+    #[must_use]
+    pub fn with_rate_limit(mut self, requests_per_second: std::num::NonZeroU32) -> Self {
+        self.caller.rate_limiter = Some(Arc::new(crate::RateLimiter::new(requests_per_second)));
+        self
+    }
+
+    /// Transport the client sends requests through, shared by all its clones.
+    pub fn transport(&self) -> &Arc<dyn Transport> {
+        &self.caller.transport
+    }
+
+    /// Instance the client was created for, [`Region::Ru`] unless set with [`Client::with_region`].
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Replaces API token, e.g. after rotation, for this client and all its clones.
+    ///
+    /// Fails if the transport does not support it, see [`Transport::set_bearer`].
+    pub fn set_token(&self, token: impl Into<String>) -> anyhow::Result<()> {
+        self.caller.transport.set_bearer(token.into())
+    }
+
+    /// Payments with caller-supplied `id` may be retries, see [`TransferData::is_duplicate`].
+    async fn execute_payment(
+        &self,
+        id: Option<u64>,
+        req: impl FnOnce(u64) -> ApiRequest<TransferData>,
+    ) -> anyhow::Result<TransferOutcome> {
+        let (id, retry) = match id {
+            Some(id) => (id, true),
+            None => (new_payment_id(), false),
+        };
+        if let Some(mut data) = self.payment_store.as_ref().and_then(|store| store.get(id)) {
+            data.duplicate = true;
+            return Ok(data.into());
+        }
+
+        // QIWI answers a repeated id with the transaction made by the first attempt, so after a
+        // lost response it can't be told apart from a new payment
+        let existing = if retry {
+            self.find_payment(id).await?
+        } else {
+            None
+        };
+        let mut data = match existing {
+            Some(data) => data,
+            None => self.caller.execute(req(id)).await?,
+        };
+        data.id = id;
+        if let Some(store) = &self.payment_store {
+            store.put(id, data.clone());
+        }
+
+        Ok(data.into())
+    }
+
+    /// Payment made with `id` among outgoing payments of the last [`PAYMENT_LOOKUP_DAYS`].
+    async fn find_payment(&self, id: u64) -> anyhow::Result<Option<TransferData>> {
+        let trm_txn_id = id.to_string();
+        let entry = self
+            .payment_history_filtered(HistoryQuery {
+                operation: HistoryOperation::Out,
+                start_date: Some(chrono::Utc::now() - chrono::Duration::days(PAYMENT_LOOKUP_DAYS)),
+                ..Default::default()
+            })
+            .try_filter(|entry| futures_util::future::ready(entry.trm_txn_id == trm_txn_id))
+            .next()
+            .await
+            .transpose()?;
+        let entry = match entry {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        // History statuses are not transfer states, translate them
+        let code = match entry.status {
+            PaymentStatus::Error => {
+                return Err(format_err!(
+                    "payment {id} was already made and failed: {}",
+                    entry.error
+                ))
+            }
+            PaymentStatus::Waiting | PaymentStatus::Success | PaymentStatus::Unknown(_) => {
+                TransferData::ACCEPTED
+            }
+        };
+
+        Ok(Some(TransferData {
+            transaction: TransferTransactionData {
+                id: entry.txn_id.to_string(),
+                state: TransferState {
+                    code: code.to_string(),
+                },
+            },
+            id,
+            duplicate: true,
+        }))
+    }
+
+    /// Runs `req`, returning response headers too, e.g. `X-Request-Id` to report to QIWI support.
+    pub async fn execute_with_headers<T>(
+        &self,
+        req: ApiRequest<T>,
+    ) -> anyhow::Result<(HeaderMap, T)>
+    where
+        T: Send + 'static,
+    {
+        self.caller.execute_with_headers(req).await
+    }
+
+    fn raw<T>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        params: HashMap<&'static str, String>,
+        body: Option<Value>,
+    ) -> BoxFuture<anyhow::Result<T>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let endpoint = endpoint.replace("{user}", &self.user.to_string());
+        self.caller
+            .execute(ApiRequest::raw(method, endpoint, params, body))
+    }
+}
+
+impl Client {
+    pub async fn profile_info(&self) -> anyhow::Result<ProfileInfo> {
+        self.caller.execute(ApiRequest::profile_info()).await
+    }
+
+    /// Currency of the default wallet account.
+    pub async fn default_currency(&self) -> anyhow::Result<penny::Currency> {
+        let user_info = self
+            .profile_info()
+            .await?
+            .user_info
+            .ok_or_else(|| format_err!("profile has no user info"))?;
+
+        u16::try_from(user_info.default_pay_currency)
+            .ok()
+            .and_then(code_to_currency)
+            .ok_or_else(|| {
+                format_err!(
+                    "unsupported default currency {}",
+                    user_info.default_pay_currency
+                )
+            })
+    }
+
+    /// Checks that the API is reachable and the token is valid.
+    pub async fn ping(&self) -> Result<(), PingError> {
+        let sections = ProfileSections {
+            auth_info: true,
+            ..ProfileSections::NONE
+        };
+        match self.profile_info_with(sections).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(match e.downcast_ref::<HttpError>() {
+                Some(HttpError {
+                    status: StatusCode::UNAUTHORIZED,
+                    ..
+                }) => PingError::TokenInvalid,
+                _ => PingError::Unavailable(e),
+            }),
+        }
+    }
+
+    /// Primes connection to the API, so that subsequent calls skip DNS and TLS setup.
+    ///
+    /// Optional, only useful right before latency-sensitive calls like a burst of transfers.
+    pub async fn warm_up(&self) -> anyhow::Result<()> {
+        Ok(self.ping().await?)
+    }
+
+    /// Profile with only the requested `sections` loaded.
+    pub async fn profile_info_with(
+        &self,
+        sections: ProfileSections,
+    ) -> anyhow::Result<ProfileInfo> {
+        self.caller
+            .execute(ApiRequest::profile_info_with(sections))
+            .await
+    }
+
+    pub async fn identification(&self) -> anyhow::Result<Identification> {
+        self.caller
+            .execute(ApiRequest::identification(&self.user.0))
+            .await
+    }
+
+    pub async fn payment_sources(&self) -> anyhow::Result<Vec<PaymentSource>> {
+        self.caller
+            .execute(ApiRequest::payment_sources(&self.user.0))
+            .await
+    }
+
+    pub async fn nickname(&self) -> anyhow::Result<Nickname> {
+        self.caller
+            .execute(ApiRequest::nickname(&self.user.0))
+            .await
+    }
+
+    pub async fn set_nickname(&self, nickname: &str) -> anyhow::Result<()> {
+        self.caller
+            .execute(ApiRequest::set_nickname(&self.user.0, nickname))
+            .await
+    }
+
+    pub async fn accounts(&self) -> anyhow::Result<Vec<AccountBalance>> {
+        self.caller
+            .execute(ApiRequest::accounts(&self.user.0))
+            .await
+    }
+
+    /// Balance of the wallet account in the currency of the client's region, see [`Region::currency`].
+    pub async fn default_balance(&self) -> anyhow::Result<BigDecimal> {
+        self.balance(self.region.currency()).await
+    }
+
+    /// Balance of the wallet account in `currency`, fails if there is no such account.
+    pub async fn balance(&self, currency: penny::Currency) -> anyhow::Result<BigDecimal> {
+        self.accounts()
+            .await?
+            .into_iter()
+            .filter(|account| account.currency == currency)
+            .find_map(|account| account.balance)
+            .map(|balance| balance.amount)
+            .ok_or_else(|| format_err!("wallet has no {currency:?} account with balance"))
+    }
+
+    /// Polls account balances every `interval`, starting immediately. Requires tokio runtime.
+    #[cfg(feature = "tokio")]
+    #[must_use = "streams do nothing unless polled"]
+    pub fn balance_stream(
+        &self,
+        interval: std::time::Duration,
+    ) -> BoxStream<anyhow::Result<Vec<AccountBalance>>> {
+        self.balance_stream_until(interval, CancellationToken::new())
+    }
+
+    /// Same as [`Client::balance_stream`], but ends once `cancel` is triggered, after the request in flight.
+    #[cfg(feature = "tokio")]
+    #[must_use = "streams do nothing unless polled"]
+    pub fn balance_stream_until(
+        &self,
+        interval: std::time::Duration,
+        cancel: CancellationToken,
+    ) -> BoxStream<anyhow::Result<Vec<AccountBalance>>> {
+        let caller = self.caller.clone();
+        let user_id = self.user.clone();
+        Box::pin(try_stream! {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                let tick = Box::pin(interval.tick());
+                let cancelled = Box::pin(cancel.cancelled());
+                if let Either::Right(_) = futures_util::future::select(tick, cancelled).await {
+                    break;
+                }
+
+                yield caller.execute(ApiRequest::accounts(&user_id.0)).await?;
+            }
+        })
+    }
+
+    /// Link to the form for topping up this wallet, to be shared or rendered as QR code.
+    pub fn topup_qr(&self) -> anyhow::Result<String> {
+        let mut url = reqwest::Url::parse(self.region.site_url())?
+            .join(&format!("payment/form/{}", ProviderId::QIWI))?;
+        url.query_pairs_mut()
+            .append_pair("extra['account']", &self.user.to_string())
+            .append_pair("blocked[0]", "account");
+        Ok(url.into())
+    }
+
+    /// [`Client::topup_qr`] rendered as PNG image.
+    #[cfg(feature = "png")]
+    pub fn topup_qr_png(&self) -> anyhow::Result<Vec<u8>> {
+        let image = qrcode::QrCode::new(self.topup_qr()?)?
+            .render::<image::Luma<u8>>()
+            .build();
+        let mut png = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageOutputFormat::Png)?;
+        Ok(png.into_inner())
+    }
+
+    /// Payment history, newest payments first.
+    pub fn payment_history(&self) -> PaymentHistory {
+        self.payment_history_filtered(Default::default())
+    }
+
+    /// Collects up to `max` history entries, or all of them if `max` is zero.
+    pub async fn payment_history_vec(
+        &self,
+        max: usize,
+    ) -> anyhow::Result<Vec<PaymentHistoryEntry>> {
+        let max = if max == 0 { usize::MAX } else { max };
+        self.payment_history().take(max).try_collect().await
+    }
+
+    /// Payment history matching `query`, newest payments first unless [`HistoryQuery::oldest_first`] is set.
+    pub fn payment_history_filtered(&self, query: HistoryQuery) -> PaymentHistory {
+        self.filtered_history(query, || false)
+    }
+
+    /// Same as [`Client::payment_history_filtered`], but ends after the current page once `cancel`
+    /// is triggered, or after the current day with [`HistoryQuery::oldest_first`].
+    #[cfg(feature = "tokio")]
+    pub fn payment_history_filtered_until(
+        &self,
+        query: HistoryQuery,
+        cancel: CancellationToken,
+    ) -> PaymentHistory {
+        self.filtered_history(query, move || cancel.is_cancelled())
+    }
+
+    fn filtered_history(
+        &self,
+        query: HistoryQuery,
+        is_cancelled: impl Fn() -> bool + Send + 'static,
+    ) -> PaymentHistory {
+        if !query.oldest_first {
+            return PaymentHistory {
+                inner: Box::pin(
+                    self.history_stream(query, None, is_cancelled)
+                        .map_ok(|(entry, _)| entry),
+                ),
+            };
+        }
+
+        let client = self.clone();
+        let inner: BoxStream<anyhow::Result<_>> = Box::pin(try_stream! {
+            let start = query.start_date.ok_or_else(|| {
+                format_err!("oldest first payment history requires start_date")
+            })?;
+            let end = query.end_date.unwrap_or_else(chrono::Utc::now);
+
+            // QIWI paginates from the newest payment, so walk forward one window at a time
+            let mut window_start = start;
+            let mut window_days = OLDEST_FIRST_WINDOW_DAYS;
+            // Payments at the boundary are returned for both adjacent windows
+            let mut previous_window = HashSet::new();
+            while window_start < end {
+                let window_end = (window_start + chrono::Duration::days(window_days)).min(end);
+                let window = HistoryQuery {
+                    start_date: Some(window_start),
+                    end_date: Some(window_end),
+                    oldest_first: false,
+                    ..query.clone()
+                };
+                let entries = client
+                    .history_stream(window, None, || false)
+                    .map_ok(|(entry, _)| entry)
+                    .try_collect::<Vec<_>>()
+                    .await?;
+
+                // Shrink back once payments get dense again, to keep the buffer small
+                window_days = if entries.len() < usize::from(HistoryQuery::MAX_ROWS) {
+                    (window_days * 2).min(OLDEST_FIRST_MAX_WINDOW_DAYS)
+                } else {
+                    OLDEST_FIRST_WINDOW_DAYS
+                };
+
+                let mut current_window = HashSet::new();
+                for entry in entries.into_iter().rev() {
+                    current_window.insert(entry.txn_id);
+                    if !previous_window.contains(&entry.txn_id) {
+                        yield entry;
+                    }
+                }
+                previous_window = current_window;
+                window_start = window_end;
+
+                if is_cancelled() {
+                    break;
+                }
+            }
+        });
+        PaymentHistory { inner }
+    }
+
+    /// Payment history with only payments to or from `providers`, filtered client-side.
+    pub fn payment_history_for_providers(&self, providers: &[ProviderId]) -> PaymentHistory {
+        let providers = providers.iter().map(|id| id.get()).collect::<HashSet<_>>();
+        PaymentHistory {
+            inner: Box::pin(self.payment_history().try_filter(move |entry| {
+                futures_util::future::ready(providers.contains(&entry.provider.id))
+            })),
+        }
+    }
+
+    /// Payment history starting at `cursor`, newest payments first, with each entry paired with the
+    /// cursor to resume after it.
+    ///
+    /// Resuming may repeat entries from the page that was being processed. Fails if
+    /// [`HistoryQuery::oldest_first`] is set, use [`Client::payment_history_filtered`] for that.
+    pub fn payment_history_from(
+        &self,
+        query: HistoryQuery,
+        cursor: Option<HistoryCursor>,
+    ) -> PaymentHistory<(PaymentHistoryEntry, Option<HistoryCursor>)> {
+        self.history_stream(query, cursor, || false)
+    }
+
+    /// Same as [`Client::payment_history_from`], but ends after the current page once `cancel` is triggered.
+    #[cfg(feature = "tokio")]
+    pub fn payment_history_until(
+        &self,
+        query: HistoryQuery,
+        cursor: Option<HistoryCursor>,
+        cancel: CancellationToken,
+    ) -> PaymentHistory<(PaymentHistoryEntry, Option<HistoryCursor>)> {
+        self.history_stream(query, cursor, move || cancel.is_cancelled())
+    }
+
+    fn history_stream(
+        &self,
+        query: HistoryQuery,
+        cursor: Option<HistoryCursor>,
+        is_cancelled: impl Fn() -> bool + Send + 'static,
+    ) -> PaymentHistory<(PaymentHistoryEntry, Option<HistoryCursor>)> {
+        let caller = self.caller.clone();
+        let user_id = self.user.clone();
+        let inner: BoxStream<anyhow::Result<_>> = Box::pin(try_stream! {
+            if query.oldest_first {
+                Err::<(), _>(format_err!(
+                    "oldest first payment history can't be resumed from a cursor"
+                ))?;
+            }
+
+            let mut cursor = cursor;
+            // QIWI may repeat transactions at the page boundary on the next page
+            let mut previous_page = HashSet::new();
+            let mut empty_pages = 0;
+            loop {
+                let mut history = caller
+                    .execute(ApiRequest::payment_history(&user_id.0, &query, cursor.clone()))
+                    .await?;
+                // Stable, keeps QIWI order of payments made at the same second
+                history.data.sort_by(|a, b| b.date.cmp(&a.date));
+
+                let next = match (history.next_txn_date, history.next_txn_id) {
+                    (Some(next_txn_date), Some(next_txn_id)) => Some(HistoryCursor {
+                        next_txn_date,
+                        next_txn_id,
+                    }),
+                    (None, None) => None,
+                    // Continue from the last entry, it is skipped as repeated boundary transaction
+                    (next_txn_date, next_txn_id) => {
+                        let fallback = history
+                            .data
+                            .last()
+                            .map(|entry| HistoryCursor {
+                                next_txn_date: qiwi_datetime(&entry.date),
+                                next_txn_id: entry.txn_id,
+                            })
+                            // Would refetch the same page forever
+                            .filter(|fallback| Some(fallback) != cursor.as_ref());
+                        tracing::warn!(
+                            ?next_txn_date,
+                            ?next_txn_id,
+                            ?fallback,
+                            "Incomplete payment history cursor, continuing from the last entry"
+                        );
+                        fallback
+                    }
+                };
+
+                let len = history.data.len();
+                let mut page = HashSet::new();
+                for (i, entry) in history.data.into_iter().enumerate() {
+                    if !page.insert(entry.txn_id) || previous_page.contains(&entry.txn_id) {
+                        continue;
+                    }
+
+                    let cursor_after = if i + 1 == len {
+                        next.clone().or_else(|| cursor.clone())
+                    } else {
+                        cursor.clone()
+                    };
+                    yield (entry, cursor_after);
+                }
+                previous_page = page;
+
+                let next = match next {
+                    Some(next) if !is_cancelled() => next,
+                    _ => break,
+                };
+
+                // Processing lag on QIWI side: empty page that still points further
+                if len == 0 {
+                    empty_pages += 1;
+                    if empty_pages >= MAX_EMPTY_HISTORY_PAGES {
+                        Err::<(), _>(format_err!(
+                            "payment history returned {MAX_EMPTY_HISTORY_PAGES} empty pages in a row"
+                        ))?;
+                    }
+                    #[cfg(feature = "tokio")]
+                    {
+                        tracing::debug!(empty_pages, "Empty payment history page, backing off");
+                        tokio::time::sleep(EMPTY_HISTORY_PAGE_DELAY * 2u32.pow(empty_pages - 1))
+                            .await;
+                    }
+                    // Refetching right away would hammer the API
+                    #[cfg(not(feature = "tokio"))]
+                    Err::<(), _>(format_err!(
+                        "payment history returned an empty page, waiting for the rest requires the `tokio` feature"
+                    ))?;
+                } else {
+                    empty_pages = 0;
+                }
+
+                cursor = Some(next);
+            }
+        });
+
+        PaymentHistory { inner }
+    }
+
+    /// GET request to an endpoint not covered by this crate. `{user}` in `endpoint` is replaced with wallet id.
+    pub async fn raw_get<T>(
+        &self,
+        endpoint: &str,
+        params: HashMap<&'static str, String>,
+    ) -> anyhow::Result<T>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        self.raw(Method::GET, endpoint, params, None).await
+    }
+
+    /// POST request to an endpoint not covered by this crate. `{user}` in `endpoint` is replaced with wallet id.
+    pub async fn raw_post<T>(
+        &self,
+        endpoint: &str,
+        params: HashMap<&'static str, String>,
+        body: Value,
+    ) -> anyhow::Result<T>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        self.raw(Method::POST, endpoint, params, Some(body)).await
+    }
+
+    pub async fn transaction(&self, txn_id: u64) -> anyhow::Result<PaymentHistoryEntry> {
+        self.caller.execute(ApiRequest::transaction(txn_id)).await
+    }
+
+    /// Downloads PDF of the bank document (payment order) for transaction `txn_id`.
+    pub async fn bank_document(
+        &self,
+        txn_id: u64,
+        payment_type: PaymentType,
+    ) -> anyhow::Result<Vec<u8>> {
+        self.caller
+            .execute(ApiRequest::bank_document(txn_id, payment_type))
+            .await
+    }
+
+    pub async fn commission_info(&self, provider: ProviderId) -> anyhow::Result<CommissionInfo> {
+        self.caller
+            .execute(ApiRequest::commission_info(provider))
+            .await
+    }
+
+    /// Providers matching free-text `query`, e.g. a service name. Use with [`ProviderId::new`].
+    pub async fn providers(&self, query: &str) -> anyhow::Result<Vec<ProviderData>> {
+        self.caller.execute(ApiRequest::providers(query)).await
+    }
+
+    /// Schema of fields to pass to [`Client::pay`] for `provider`.
+    pub async fn provider_form(&self, provider: ProviderId) -> anyhow::Result<ProviderForm> {
+        self.caller
+            .execute(ApiRequest::provider_form(provider))
+            .await
+    }
+
+    pub async fn commission_info_for_account(
+        &self,
+        provider: ProviderId,
+        account: String,
+    ) -> anyhow::Result<CommissionInfo> {
+        self.caller
+            .execute(ApiRequest::commission_info_for_account(provider, account))
+            .await
+    }
+
+    /// Commission for paying `amount` in `currency` from the wallet account in the same currency.
+    pub async fn commission_quote(
+        &self,
+        provider: ProviderId,
+        account: PhoneNumber,
+        amount: BigDecimal,
+        currency: penny::Currency,
+    ) -> anyhow::Result<Money> {
+        self.caller
+            .execute(ApiRequest::commission_quote(
+                provider, account, amount, currency,
+            ))
+            .await
+    }
+
+    /// Converted amount and rate net of fees for receiving `amount` in `to` currency from `from` account.
+    pub async fn conversion_quote(
+        &self,
+        from: penny::Currency,
+        to: penny::Currency,
+        amount: BigDecimal,
+    ) -> anyhow::Result<ConversionQuote> {
+        self.caller
+            .execute(ApiRequest::conversion_quote(&self.user.0, from, to, amount))
+            .await
+    }
+
+    /// Pays `sum` in `currency` to `provider`, charging wallet account in `from_currency`.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn pay(
+        &self,
+        provider: ProviderId,
+        sum: BigDecimal,
+        currency: penny::Currency,
+        from_currency: penny::Currency,
+        fields: HashMap<String, String>,
+        comment: Option<Comment>,
+        id: Option<u64>,
+    ) -> anyhow::Result<TransferOutcome> {
+        validate_payment(provider, &sum, &fields)?;
+
+        self.execute_payment(id, move |id| {
+            ApiRequest::pay(
+                provider,
+                sum,
+                currency,
+                from_currency,
+                fields,
+                comment,
+                Some(id),
+            )
+        })
+        .await
+    }
+
+    /// Pays a tax or fine of `sum` rubles. UIN is verified when constructing [`Uin`].
+    pub async fn pay_tax(
+        &self,
+        payment: &GovernmentPayment,
+        sum: BigDecimal,
+        id: Option<u64>,
+    ) -> anyhow::Result<TransferOutcome> {
+        self.pay(
+            ProviderId::GOVERNMENT,
+            sum,
+            penny::Currency::RUB,
+            penny::Currency::RUB,
+            payment.fields(),
+            None,
+            id,
+        )
+        .await
+    }
+
+    /// Repeats past payment to the same provider and account with a fresh payment id.
+    pub async fn repeat_payment(&self, txn_id: u64) -> anyhow::Result<TransferOutcome> {
+        let entry = self.transaction(txn_id).await?;
+        if !entry.repeat_payment_enabled {
+            return Err(format_err!("payment {txn_id} cannot be repeated"));
+        }
+
+        let currency = entry.sum.currency.known().ok_or_else(|| {
+            format_err!(
+                "payment {txn_id} is in unsupported currency {}",
+                entry.sum.currency
+            )
+        })?;
+        // Older payments may have comments QIWI would no longer accept
+        let comment = Comment::new_lossy(&entry.comment);
+        if comment.as_str() != entry.comment {
+            tracing::warn!(
+                txn_id,
+                "Comment of repeated payment sanitized to fit QIWI limits"
+            );
+        }
+
+        self.pay(
+            ProviderId(entry.provider.id),
+            entry.sum.amount,
+            currency,
+            currency,
+            HashMap::from([(
+                "account".to_string(),
+                entry.account.trim_start_matches('+').to_string(),
+            )]),
+            Some(comment),
+            None,
+        )
+        .await
+    }
+
+    pub async fn transfer(
+        &self,
+        id: Option<u64>,
+        amount: BigDecimal,
+        from_currency: penny::Currency,
+        direction: TransferDirection,
+        comment: Comment,
+    ) -> anyhow::Result<TransferOutcome> {
+        let (provider, _, fields) = transfer_target(direction.clone());
+        validate_payment(provider, &amount, &fields)?;
+
+        self.execute_payment(id, move |id| {
+            ApiRequest::transfer(Some(id), amount, from_currency, direction, comment)
+        })
+        .await
+    }
+
+    /// Completes payment that returned [`TransferOutcome::ConfirmationRequired`] with `id` and `txn_id`.
+    pub async fn confirm_transfer(
+        &self,
+        id: u64,
+        txn_id: &str,
+        code: &str,
+    ) -> anyhow::Result<TransferData> {
+        let mut data = self
+            .caller
+            .execute(ApiRequest::confirm_transfer(txn_id, code))
+            .await?;
+        data.id = id;
+        // Retries with the same id must not report the payment as still pending
+        if let Some(store) = &self.payment_store {
+            store.put(id, data.clone());
+        }
+
+        Ok(data)
+    }
+
+    /// Same checks and commission quote as [`Client::transfer`] would need, but without moving money.
+    pub async fn transfer_preview(
+        &self,
+        id: Option<u64>,
+        amount: BigDecimal,
+        from_currency: penny::Currency,
+        direction: TransferDirection,
+        comment: Comment,
+    ) -> anyhow::Result<TransferPreview> {
+        let (provider, currency, fields) = transfer_target(direction.clone());
+        validate_payment(provider, &amount, &fields)?;
+
+        let id = id.unwrap_or_else(new_payment_id);
+        let account = fields
+            .get("account")
+            .cloned()
+            .ok_or_else(|| format_err!("transfer has no destination account"))?;
+        let commission = self
+            .caller
+            .execute(ApiRequest::online_commission(
+                provider,
+                account,
+                amount.clone(),
+                currency,
+                from_currency,
+            ))
+            .await?;
+
+        Ok(TransferPreview {
+            id,
+            request: ApiRequest::transfer(Some(id), amount, from_currency, direction, comment),
+            commission,
+        })
+    }
+}
+
+/// Checks shared by all payment paths, done before anything is sent.
+fn validate_payment(
+    provider: ProviderId,
+    amount: &BigDecimal,
+    fields: &HashMap<String, String>,
+) -> anyhow::Result<()> {
+    check_amount(amount)?;
+
+    let missing = provider.requirements().missing_fields(fields);
+    if !missing.is_empty() {
+        return Err(format_err!(
+            "missing fields required by provider {provider}: {}",
+            missing.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Client for P2P bills API, authorized with its own secret key.
+#[derive(Clone, Debug)]
+pub struct BillClient {
+    caller: CallerWrapper,
+}
+
+impl BillClient {
+    pub fn new(secret_key: impl Into<String>) -> anyhow::Result<Self> {
+        Ok(Self::with_transport(
+            RemoteCaller::builder()
+                .addr("https://api.qiwi.com")
+                .bearer(secret_key)
+                .build()?,
+        ))
+    }
+
+    #[must_use]
+    pub fn with_transport(transport: impl Transport) -> Self {
+        Self {
+            caller: CallerWrapper::new(transport),
+        }
+    }
+
+    /// Fails if amount is not positive or has more than 2 decimal places.
+    pub async fn create_bill(&self, bill: BillRequest) -> anyhow::Result<Bill> {
+        check_amount(&bill.amount)?;
+        self.caller.execute(ApiRequest::create_bill(bill)).await
+    }
+
+    pub async fn bill_status(&self, bill_id: &str) -> anyhow::Result<Bill> {
+        self.caller.execute(ApiRequest::bill_status(bill_id)).await
+    }
+
+    /// Cancels unpaid bill, returning its final state.
+    pub async fn reject_bill(&self, bill_id: &str) -> anyhow::Result<Bill> {
+        self.caller.execute(ApiRequest::reject_bill(bill_id)).await
+    }
+}
@@ -2,14 +2,42 @@ use bigdecimal::*;
 use chrono::prelude::*;
 use derive_more::{Display, FromStr};
 use phonenumber::PhoneNumber;
-use serde::{Deserialize, Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
-use std::{collections::HashMap, fmt::Debug, net::IpAddr};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::{self, Debug, Formatter},
+    net::IpAddr,
+};
 
 #[derive(Clone, Debug, Display)]
 #[display(fmt = "{}{}", self.0.code().value(), self.0.national())]
 pub struct QiwiUser(pub(crate) PhoneNumber);
 
+/// Country calling codes of regions where QIWI Wallet can be registered.
+const QIWI_CALLING_CODES: &[u16] = &[
+    7, 370, 371, 372, 373, 374, 375, 380, 992, 994, 995, 996, 998,
+];
+
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("{0} is not a valid QIWI wallet number")]
+pub struct InvalidQiwiUser(pub PhoneNumber);
+
+impl QiwiUser {
+    /// Checks that `phone` is a valid number from a region supported by QIWI.
+    pub fn try_new(phone: PhoneNumber) -> Result<Self, InvalidQiwiUser> {
+        if phonenumber::is_valid(&phone) && QIWI_CALLING_CODES.contains(&phone.code().value()) {
+            Ok(Self(phone))
+        } else {
+            Err(InvalidQiwiUser(phone))
+        }
+    }
+
+    pub fn phone(&self) -> &PhoneNumber {
+        &self.0
+    }
+}
+
 impl Serialize for QiwiUser {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -23,6 +51,184 @@ impl Serialize for QiwiUser {
 #[display(fmt = "{}", self.0.info().number())]
 pub struct QiwiCurrency(pub(crate) penny::Currency);
 
+/// ISO 4217 numeric code QIWI uses to identify `currency`.
+pub fn currency_to_code(currency: penny::Currency) -> u16 {
+    QiwiCurrency(currency)
+        .to_string()
+        .parse()
+        .expect("ISO 4217 numeric codes fit into u16")
+}
+
+/// Currencies QIWI wallets can operate with.
+const QIWI_CURRENCIES: [penny::Currency; 4] = [
+    penny::Currency::RUB,
+    penny::Currency::USD,
+    penny::Currency::EUR,
+    penny::Currency::KZT,
+];
+
+/// Reverse of [`currency_to_code`], limited to currencies supported by QIWI.
+pub fn code_to_currency(code: u16) -> Option<penny::Currency> {
+    QIWI_CURRENCIES
+        .into_iter()
+        .find(|&currency| currency_to_code(currency) == code)
+}
+
+/// Regional QIWI Wallet instance.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Region {
+    #[default]
+    Ru,
+    Kz,
+}
+
+impl Region {
+    /// API base URL of this instance.
+    pub fn base_url(&self) -> &'static str {
+        match self {
+            Self::Ru => "https://edge.qiwi.com",
+            Self::Kz => "https://edge.qiwi.kz",
+        }
+    }
+
+    /// Website of this instance, hosting payment forms.
+    pub fn site_url(&self) -> &'static str {
+        match self {
+            Self::Ru => "https://qiwi.com",
+            Self::Kz => "https://qiwi.kz",
+        }
+    }
+
+    /// Currency wallets of this instance operate in by default.
+    pub fn currency(&self) -> penny::Currency {
+        match self {
+            Self::Ru => penny::Currency::RUB,
+            Self::Kz => penny::Currency::KZT,
+        }
+    }
+}
+
+/// Parses ISO 4217 code of a QIWI-supported currency, either numeric (`643`) or alphabetic (`RUB`).
+pub fn parse_currency(code: &str) -> Option<penny::Currency> {
+    if let Ok(code) = code.parse() {
+        return code_to_currency(code);
+    }
+
+    // Variant names of `penny::Currency` are ISO 4217 alphabetic codes
+    QIWI_CURRENCIES
+        .into_iter()
+        .find(|currency| format!("{currency:?}").eq_ignore_ascii_case(code))
+}
+
+/// Currency of an amount or account reported by QIWI.
+///
+/// Codes not supported by this crate are kept as is, so that a single payment in an exotic currency
+/// does not fail the whole response.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CurrencyCode {
+    Known(penny::Currency),
+    /// ISO 4217 code as received, numeric or alphabetic.
+    Unknown(String),
+}
+
+impl CurrencyCode {
+    /// `None` for currencies not supported by this crate.
+    pub fn known(&self) -> Option<penny::Currency> {
+        match self {
+            Self::Known(currency) => Some(*currency),
+            Self::Unknown(_) => None,
+        }
+    }
+}
+
+impl From<penny::Currency> for CurrencyCode {
+    fn from(currency: penny::Currency) -> Self {
+        Self::Known(currency)
+    }
+}
+
+impl PartialEq<penny::Currency> for CurrencyCode {
+    fn eq(&self, other: &penny::Currency) -> bool {
+        self.known() == Some(*other)
+    }
+}
+
+/// Formats as ISO 4217 alphabetic code, e.g. `RUB`, or as received for unknown currencies.
+impl fmt::Display for CurrencyCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            // Variant names of `penny::Currency` are ISO 4217 alphabetic codes
+            Self::Known(currency) => write!(f, "{currency:?}"),
+            Self::Unknown(code) => write!(f, "{code}"),
+        }
+    }
+}
+
+/// (De)serializes [`CurrencyCode`] as ISO 4217 numeric code.
+///
+/// Responses may carry the code as a number or a string, numeric or alphabetic, so all forms are accepted.
+pub(crate) mod numeric_currency {
+    use super::*;
+    use serde::Deserializer;
+
+    #[derive(Debug, Deserialize)]
+    #[serde(untagged)]
+    enum Code {
+        Number(u16),
+        String(String),
+    }
+
+    pub fn serialize<S>(currency: &CurrencyCode, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match currency {
+            CurrencyCode::Known(currency) => serializer.serialize_u16(currency_to_code(*currency)),
+            CurrencyCode::Unknown(code) => match code.parse::<u16>() {
+                Ok(code) => serializer.serialize_u16(code),
+                Err(_) => serializer.serialize_str(code),
+            },
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<CurrencyCode, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(match Code::deserialize(deserializer)? {
+            Code::Number(code) => code_to_currency(code)
+                .map(CurrencyCode::Known)
+                .unwrap_or_else(|| CurrencyCode::Unknown(code.to_string())),
+            Code::String(code) => parse_currency(&code)
+                .map(CurrencyCode::Known)
+                .unwrap_or(CurrencyCode::Unknown(code)),
+        })
+    }
+}
+
+/// (De)serializes `penny::Currency` as ISO 4217 alphabetic code, as used by the bill API.
+pub(crate) mod alpha_currency {
+    use super::*;
+    use serde::de::Error;
+
+    pub fn serialize<S>(currency: &penny::Currency, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("{currency:?}"))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<penny::Currency, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let code = String::deserialize(deserializer)?;
+        parse_currency(&code).ok_or_else(|| D::Error::custom(format!("unknown currency {code}")))
+    }
+}
+
 impl Serialize for QiwiCurrency {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -34,6 +240,8 @@ impl Serialize for QiwiCurrency {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct MobilePinInfo {
     pub mobile_pin_used: bool,
     pub last_mobile_pin_change: String,
@@ -42,6 +250,8 @@ pub struct MobilePinInfo {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct PassInfo {
     pub password_used: bool,
     pub last_pass_change: String,
@@ -50,28 +260,162 @@ pub struct PassInfo {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct PinInfo {
     pub pin_used: bool,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
+#[non_exhaustive]
 pub enum IdentificationLevel {
     Anonymous,
     Simple,
     Verified,
     Full,
+    #[serde(other)]
+    Unknown,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct IdentificationInfo {
-    pub bank_alias: String,
+    pub bank_alias: BankAlias,
     pub identification_level: IdentificationLevel,
 }
 
+/// Bank that performed identification.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+#[non_exhaustive]
+pub enum BankAlias {
+    Qiwi,
+    Unknown(String),
+}
+
+impl BankAlias {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Qiwi => "QIWI",
+            Self::Unknown(v) => v,
+        }
+    }
+}
+
+impl From<String> for BankAlias {
+    fn from(v: String) -> Self {
+        match v.as_str() {
+            "QIWI" => Self::Qiwi,
+            _ => Self::Unknown(v),
+        }
+    }
+}
+
+impl From<BankAlias> for String {
+    fn from(v: BankAlias) -> Self {
+        v.as_str().to_string()
+    }
+}
+
+/// Document checked during identification.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+#[non_exhaustive]
+pub enum IdentificationCheck {
+    Passport,
+    Inn,
+    Snils,
+    Oms,
+    Unknown(String),
+}
+
+impl IdentificationCheck {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Passport => "PASSPORT",
+            Self::Inn => "INN",
+            Self::Snils => "SNILS",
+            Self::Oms => "OMS",
+            Self::Unknown(v) => v,
+        }
+    }
+}
+
+impl From<String> for IdentificationCheck {
+    fn from(v: String) -> Self {
+        match v.as_str() {
+            "PASSPORT" => Self::Passport,
+            "INN" => Self::Inn,
+            "SNILS" => Self::Snils,
+            "OMS" => Self::Oms,
+            _ => Self::Unknown(v),
+        }
+    }
+}
+
+impl From<IdentificationCheck> for String {
+    fn from(v: IdentificationCheck) -> Self {
+        v.as_str().to_string()
+    }
+}
+
+/// Whether the user has completed KYC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum IdentificationStatus {
+    NotVerified,
+    /// Simplified identification, full one requires submitting documents.
+    Partial,
+    Verified,
+}
+
+impl From<&IdentificationLevel> for IdentificationStatus {
+    fn from(level: &IdentificationLevel) -> Self {
+        match level {
+            IdentificationLevel::Anonymous | IdentificationLevel::Unknown => Self::NotVerified,
+            IdentificationLevel::Simple | IdentificationLevel::Verified => Self::Partial,
+            IdentificationLevel::Full => Self::Verified,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
+pub struct Identification {
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub level: IdentificationLevel,
+    pub birth_date: Option<String>,
+    pub first_name: Option<String>,
+    pub middle_name: Option<String>,
+    pub last_name: Option<String>,
+    pub passport: Option<String>,
+    pub inn: Option<String>,
+    pub snils: Option<String>,
+    pub oms: Option<String>,
+    /// Not sent for anonymous wallets.
+    #[serde(default, alias = "bankAlias")]
+    pub bank: Option<BankAlias>,
+    /// Documents verified so far, not sent for anonymous wallets.
+    #[serde(default)]
+    pub checks: Option<Vec<IdentificationCheck>>,
+}
+
+impl Identification {
+    pub fn status(&self) -> IdentificationStatus {
+        (&self.level).into()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct UserInfo {
     pub default_pay_currency: u64,
     pub default_pay_source: u64,
@@ -85,60 +429,453 @@ pub struct UserInfo {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct ContractInfo {
     pub blocked: bool,
     pub contract_id: u64,
+    #[serde(deserialize_with = "lenient_datetime")]
     pub creation_date: DateTime<Utc>,
     pub features: Vec<Value>,
-    pub identification_info: Vec<IdentificationLevel>,
-    pub user_info: UserInfo,
+    pub identification_info: Vec<IdentificationInfo>,
+}
+
+/// Wallet account id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Display, FromStr, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PersonId(pub(crate) u64);
+
+impl From<u64> for PersonId {
+    fn from(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+impl From<PersonId> for u64 {
+    fn from(id: PersonId) -> Self {
+        id.0
+    }
+}
+
+impl PersonId {
+    pub const fn new(id: u64) -> Self {
+        Self(id)
+    }
+
+    pub const fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDateTime {
+    Millis(i64),
+    String(String),
+}
+
+/// Accepts RFC 3339, epoch milliseconds and `YYYY-MM-DD HH:MM:SS` in Moscow time, all seen in responses.
+fn lenient_datetime<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    match RawDateTime::deserialize(deserializer)? {
+        RawDateTime::Millis(millis) => Utc
+            .timestamp_millis_opt(millis)
+            .single()
+            .ok_or_else(|| D::Error::custom(format!("timestamp {millis} is out of range"))),
+        RawDateTime::String(s) => DateTime::parse_from_rfc3339(&s)
+            .map(|dt| dt.with_timezone(&Utc))
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S")
+                    .ok()
+                    .and_then(|dt| dt.and_local_timezone(chrono_tz::Europe::Moscow).single())
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .ok_or_else(|| D::Error::custom(format!("invalid datetime {s:?}")))
+            }),
+    }
+}
+
+fn lenient_datetime_opt<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Wrapper(#[serde(deserialize_with = "lenient_datetime")] DateTime<Utc>);
+
+    Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(dt)| dt))
+}
+
+/// QIWI may send empty string or a placeholder instead of an address.
+fn lenient_ip<'de, D>(deserializer: D) -> Result<Option<IpAddr>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.and_then(|ip| ip.parse().ok()))
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct AuthInfo {
-    pub person_id: u64,
+    pub person_id: PersonId,
+    #[serde(deserialize_with = "lenient_datetime")]
     pub registration_date: DateTime<Utc>,
     pub bound_email: Option<String>,
-    pub ip: IpAddr,
+    /// Missing for freshly created wallets.
+    #[serde(default, deserialize_with = "lenient_ip")]
+    pub ip: Option<IpAddr>,
+    #[serde(default, deserialize_with = "lenient_datetime_opt")]
     pub last_login_date: Option<DateTime<Utc>>,
     pub mobile_pin_info: MobilePinInfo,
     pub pass_info: PassInfo,
     pub pin_info: PinInfo,
-    pub contract_info: Option<ContractInfo>,
+}
+
+/// Sections of profile to request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProfileSections {
+    pub auth_info: bool,
+    pub contract_info: bool,
+    pub user_info: bool,
+}
+
+impl ProfileSections {
+    pub const ALL: Self = Self {
+        auth_info: true,
+        contract_info: true,
+        user_info: true,
+    };
+    pub const NONE: Self = Self {
+        auth_info: false,
+        contract_info: false,
+        user_info: false,
+    };
+}
+
+impl Default for ProfileSections {
+    fn default() -> Self {
+        Self::ALL
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct ProfileInfo {
-    pub auth_info: AuthInfo,
+    /// Sections are absent unless requested with [`ProfileSections`].
+    pub auth_info: Option<AuthInfo>,
+    pub contract_info: Option<ContractInfo>,
+    pub user_info: Option<UserInfo>,
+}
+
+/// Formats as `Wallet 79991234567, registered 2020-01-01`.
+impl fmt::Display for ProfileInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.auth_info {
+            Some(auth_info) => write!(
+                f,
+                "Wallet {}, registered {}",
+                auth_info.person_id,
+                auth_info.registration_date.date_naive()
+            ),
+            None => write!(f, "Wallet profile without auth info"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
+pub struct PaymentSourceType {
+    pub id: String,
+    pub title: String,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
+pub struct PaymentSource {
+    pub alias: String,
+    pub fs_alias: String,
+    pub title: String,
+    #[serde(rename = "type")]
+    pub source_type: PaymentSourceType,
+    #[serde(with = "numeric_currency")]
+    pub currency: CurrencyCode,
+    pub default_account: bool,
+}
+
+/// Wallet account with its balance.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
+pub struct AccountBalance {
+    pub alias: String,
+    pub title: String,
+    #[serde(with = "numeric_currency")]
+    pub currency: CurrencyCode,
+    pub has_balance: bool,
+    /// Absent for accounts without balance, e.g. linked cards.
+    pub balance: Option<Money>,
+    pub default_account: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub(crate) struct AccountsData<T> {
+    pub accounts: Vec<T>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+#[non_exhaustive]
 pub enum PaymentType {
     In,
     Out,
     QiwiCard,
+    /// Value not known to this crate.
+    Unknown(String),
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+impl PaymentType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::In => "IN",
+            Self::Out => "OUT",
+            Self::QiwiCard => "QIWI_CARD",
+            Self::Unknown(v) => v,
+        }
+    }
+}
+
+impl From<String> for PaymentType {
+    fn from(v: String) -> Self {
+        match v.as_str() {
+            "IN" => Self::In,
+            "OUT" => Self::Out,
+            "QIWI_CARD" => Self::QiwiCard,
+            _ => Self::Unknown(v),
+        }
+    }
+}
+
+impl From<PaymentType> for String {
+    fn from(v: PaymentType) -> Self {
+        v.as_str().to_string()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+#[non_exhaustive]
 pub enum PaymentStatus {
     Waiting,
     Success,
     Error,
+    /// Value not known to this crate.
+    Unknown(String),
 }
 
+impl PaymentStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Waiting => "WAITING",
+            Self::Success => "SUCCESS",
+            Self::Error => "ERROR",
+            Self::Unknown(v) => v,
+        }
+    }
+}
+
+impl PaymentStatus {
+    /// Label for UI in `lang`, independent of QIWI wording.
+    pub fn display(&self, lang: Language) -> &'static str {
+        match (lang, self) {
+            (Language::En, Self::Waiting) => "Pending",
+            (Language::En, Self::Success) => "Completed",
+            (Language::En, Self::Error) => "Failed",
+            (Language::En, Self::Unknown(_)) => "Unknown",
+            (Language::Ru, Self::Waiting) => "В обработке",
+            (Language::Ru, Self::Success) => "Выполнен",
+            (Language::Ru, Self::Error) => "Ошибка",
+            (Language::Ru, Self::Unknown(_)) => "Неизвестно",
+        }
+    }
+}
+
+/// Language of labels provided by this crate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Language {
+    #[default]
+    En,
+    Ru,
+}
+
+impl From<String> for PaymentStatus {
+    fn from(v: String) -> Self {
+        match v.as_str() {
+            "WAITING" => Self::Waiting,
+            "SUCCESS" => Self::Success,
+            "ERROR" => Self::Error,
+            _ => Self::Unknown(v),
+        }
+    }
+}
+
+impl From<PaymentStatus> for String {
+    fn from(v: PaymentStatus) -> Self {
+        v.as_str().to_string()
+    }
+}
+
+/// Public wallet identifier that can be used instead of phone number to receive transfers.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct PaymentSumData {
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
+pub struct Nickname {
+    /// Not set yet if empty.
+    pub nickname: Option<String>,
+    pub can_change: bool,
+    pub description: String,
+}
+
+/// Deserializes `BigDecimal` from a string or a JSON number.
+///
+/// Floats are converted through their shortest string representation, so that e.g. `0.1` does not
+/// turn into the exact binary value `0.1000000000000000055511151231257827`.
+fn lenient_decimal<'de, D>(deserializer: D) -> Result<BigDecimal, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct Visitor;
+
+    impl<'de> serde::de::Visitor<'de> for Visitor {
+        type Value = BigDecimal;
+
+        fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            f.write_str("a decimal number or string")
+        }
+
+        fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            Ok(v.into())
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(v.into())
+        }
+
+        fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Self::Value, E> {
+            self.visit_str(&v.to_string())
+        }
+
+        fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            v.parse()
+                .map_err(|_| E::custom(format!("invalid decimal {v:?}")))
+        }
+    }
+
+    deserializer.deserialize_any(Visitor)
+}
+
+/// Amount of money in a particular currency.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub struct Money {
+    #[serde(deserialize_with = "lenient_decimal")]
     pub amount: BigDecimal,
-    pub currency: String,
+    #[serde(with = "numeric_currency")]
+    pub currency: CurrencyCode,
+}
+
+impl Money {
+    pub fn new(amount: BigDecimal, currency: impl Into<CurrencyCode>) -> Self {
+        Self {
+            amount,
+            currency: currency.into(),
+        }
+    }
+
+    /// Sum of two amounts, `None` if currencies differ.
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        (self.currency == other.currency)
+            .then(|| Self::new(&self.amount + &other.amount, self.currency.clone()))
+    }
+
+    /// Difference of two amounts, `None` if currencies differ.
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        (self.currency == other.currency)
+            .then(|| Self::new(&self.amount - &other.amount, self.currency.clone()))
+    }
+}
+
+/// Formats as `500.00 RUB`.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount.with_scale(2), self.currency)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum InvalidAmount {
+    #[error("{0:?} is not a decimal number")]
+    Malformed(String),
+    #[error("amount must be positive")]
+    NotPositive,
+    #[error("amount must have at most 2 decimal places")]
+    TooPrecise,
+}
+
+/// Parses payment amount, e.g. `100` or `99.95`.
+///
+/// Only plain positive decimals with at most 2 fractional digits are accepted, so that the amount
+/// is never rounded by QIWI. Exponents and signs are rejected.
+pub fn parse_amount(s: &str) -> Result<BigDecimal, InvalidAmount> {
+    let malformed = || InvalidAmount::Malformed(s.to_string());
+
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    if int.is_empty()
+        || !int.bytes().all(|b| b.is_ascii_digit())
+        || !frac.bytes().all(|b| b.is_ascii_digit())
+        || (s.contains('.') && frac.is_empty())
+    {
+        return Err(malformed());
+    }
+
+    let amount = s.parse::<BigDecimal>().map_err(|_| malformed())?;
+    check_amount(&amount)?;
+
+    Ok(amount)
+}
+
+/// Checks that payment amount is positive and has at most 2 fractional digits.
+pub fn check_amount(amount: &BigDecimal) -> Result<(), InvalidAmount> {
+    if *amount <= BigDecimal::zero() {
+        return Err(InvalidAmount::NotPositive);
+    }
+    if amount.with_scale(2) != *amount {
+        return Err(InvalidAmount::TooPrecise);
+    }
+
+    Ok(())
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct ProviderData {
     pub id: u64,
     pub short_name: String,
@@ -151,9 +888,12 @@ pub struct ProviderData {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct PaymentHistoryEntry {
     pub txn_id: u64,
-    pub person_id: u64,
+    pub person_id: PersonId,
+    #[serde(deserialize_with = "lenient_datetime")]
     pub date: DateTime<Utc>,
     pub error_code: u64,
     pub error: String,
@@ -163,11 +903,12 @@ pub struct PaymentHistoryEntry {
     pub status_text: String,
     pub trm_txn_id: String,
     pub account: String,
-    pub sum: PaymentSumData,
-    pub commission: PaymentSumData,
-    pub total: PaymentSumData,
+    pub sum: Money,
+    pub commission: Money,
+    pub total: Money,
     pub provider: ProviderData,
     pub comment: String,
+    #[serde(deserialize_with = "lenient_decimal")]
     pub currency_rate: BigDecimal,
     pub extras: HashMap<String, Value>,
     pub cheque_ready: bool,
@@ -178,17 +919,192 @@ pub struct PaymentHistoryEntry {
     pub regular_payment_enabled: bool,
 }
 
+impl PaymentHistoryEntry {
+    /// Localized label of `status`, unlike `status_text` not affected by QIWI wording changes.
+    pub fn status_display(&self, lang: Language) -> &'static str {
+        self.status.display(lang)
+    }
+
+    /// Payment sum as a change of balance: negative for outgoing payments, positive for incoming.
+    ///
+    /// Payments of unknown type are returned as is.
+    pub fn signed_amount(&self) -> BigDecimal {
+        match self.payment_type {
+            PaymentType::Out | PaymentType::QiwiCard => -&self.sum.amount,
+            PaymentType::In | PaymentType::Unknown(_) => self.sum.amount.clone(),
+        }
+    }
+}
+
+/// Formats as `Payment #123 OUT 500.00 RUB to +79991234567 [SUCCESS]`.
+impl fmt::Display for PaymentHistoryEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let direction = match self.payment_type {
+            PaymentType::In => "from",
+            _ => "to",
+        };
+        write!(
+            f,
+            "Payment #{} {} {} {direction} {} [{}]",
+            self.txn_id,
+            self.payment_type.as_str(),
+            self.sum,
+            self.account,
+            self.status.as_str()
+        )
+    }
+}
+
+/// Totals of incoming and outgoing payments per currency, as `(in, out)`.
+///
+/// Entries can be pre-filtered, e.g. those collected with `Client::payment_history_vec`.
+/// Payments of unknown type are skipped.
+pub fn sum_by_currency<'a>(
+    entries: impl Iterator<Item = &'a PaymentHistoryEntry>,
+) -> HashMap<CurrencyCode, (BigDecimal, BigDecimal)> {
+    let mut totals = HashMap::<_, (BigDecimal, BigDecimal)>::new();
+    for entry in entries {
+        let incoming = match entry.payment_type {
+            PaymentType::In => true,
+            PaymentType::Out | PaymentType::QiwiCard => false,
+            PaymentType::Unknown(_) => continue,
+        };
+
+        let (total_in, total_out) = totals.entry(entry.sum.currency.clone()).or_default();
+        if incoming {
+            *total_in += &entry.sum.amount;
+        } else {
+            *total_out += &entry.sum.amount;
+        }
+    }
+
+    totals
+}
+
+/// Formats a timestamp the way QIWI expects it in query parameters, e.g. `2024-01-01T00:00:00+03:00`.
+///
+/// QIWI operates in Moscow time, so the timestamp is converted to `Europe/Moscow` before formatting.
+pub fn qiwi_datetime(dt: &DateTime<Utc>) -> String {
+    let msk = dt.with_timezone(&chrono_tz::Europe::Moscow);
+    msk.with_timezone(&msk.offset().fix())
+        .to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+/// Payment history filter.
+///
+/// Boundaries are absolute instants; QIWI interprets them in Moscow time (UTC+3),
+/// so a calendar day in MSK starts at 21:00 UTC of the previous day.
+#[derive(Clone, Debug, Default)]
+pub struct HistoryQuery {
+    /// Page size, defaults to [`HistoryQuery::MAX_ROWS`]. Clamped to `1..=MAX_ROWS` when sent.
+    pub rows: Option<u8>,
+    pub start_date: Option<DateTime<Utc>>,
+    pub end_date: Option<DateTime<Utc>>,
+    /// Only payments from these sources, all sources if empty.
+    pub sources: BTreeSet<FundingSource>,
+    /// Filtered by QIWI, unlike providers.
+    pub operation: HistoryOperation,
+    /// Yield oldest payments first instead of newest first.
+    ///
+    /// Requires `start_date`. History is fetched forward in date ranges, each buffered in memory
+    /// before its entries are yielded. Ranges start at one day and double up to 32 days while they
+    /// hold less than a page, so sparse history takes about one request per month, dense history one
+    /// or more per day. Cursor-based `Client::payment_history_from` fails if it is set.
+    pub oldest_first: bool,
+}
+
+impl HistoryQuery {
+    /// Largest page size accepted by QIWI.
+    pub const MAX_ROWS: u8 = 50;
+}
+
+/// Source of funds of a payment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FundingSource {
+    QiwiRub,
+    QiwiUsd,
+    QiwiEur,
+    /// Linked and QIWI cards.
+    Card,
+    /// Mobile operator account.
+    Mobile,
+}
+
+impl FundingSource {
+    pub const ALL: [Self; 5] = [
+        Self::QiwiRub,
+        Self::QiwiUsd,
+        Self::QiwiEur,
+        Self::Card,
+        Self::Mobile,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::QiwiRub => "QW_RUB",
+            Self::QiwiUsd => "QW_USD",
+            Self::QiwiEur => "QW_EUR",
+            Self::Card => "CARD",
+            Self::Mobile => "MK",
+        }
+    }
+}
+
+/// Direction of payments to request from history, see [`PaymentType`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum HistoryOperation {
+    #[default]
+    All,
+    In,
+    Out,
+    QiwiCard,
+}
+
+impl HistoryOperation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::All => "ALL",
+            Self::In => "IN",
+            Self::Out => "OUT",
+            Self::QiwiCard => "QIWI_CARD",
+        }
+    }
+}
+
+/// Position in payment history, can be persisted to resume pagination later.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryCursor {
+    pub next_txn_date: String,
+    pub next_txn_id: u64,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct PaymentHistoryData {
     pub data: Vec<PaymentHistoryEntry>,
     pub next_txn_id: Option<u64>,
     pub next_txn_date: Option<String>,
 }
 
-#[derive(Clone, Copy, Debug, Display, FromStr, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Display, FromStr, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct ProviderId(pub(crate) u64);
 
+impl From<u64> for ProviderId {
+    fn from(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+impl From<ProviderId> for u64 {
+    fn from(id: ProviderId) -> Self {
+        id.0
+    }
+}
+
 impl ProviderId {
     pub const QIWI: Self = Self(99);
     pub const VISA_RU: Self = Self(1963);
@@ -201,32 +1117,266 @@ impl ProviderId {
     pub const PROMSVYAZBANK: Self = Self(821);
     pub const RUSSIAN_STANDARD: Self = Self(815);
     pub const OTHER_BANK: Self = Self(1717);
+    /// Payment by requisites to government bodies: taxes, fines, duties.
+    pub const GOVERNMENT: Self = Self(28004);
+    /// Conversion between accounts of the same wallet.
+    pub const CONVERSION: Self = Self(1099);
+
+    pub const fn new(id: u64) -> Self {
+        Self(id)
+    }
+
+    pub const fn get(&self) -> u64 {
+        self.0
+    }
+
+    /// Payment fields this provider is known to require.
+    pub fn requirements(&self) -> ProviderRequirements {
+        let required_fields: &[_] = match *self {
+            Self::OTHER_BANK => &["account", "bik", "name", "urgent"],
+            Self::GOVERNMENT => &["account", "extra_to_bik", "name", "UIN", "payment_purpose"],
+            _ => &["account"],
+        };
+
+        ProviderRequirements { required_fields }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ProviderRequirements {
+    pub required_fields: &'static [&'static str],
+}
+
+impl ProviderRequirements {
+    /// Required fields absent from `fields`.
+    pub fn missing_fields(&self, fields: &HashMap<String, String>) -> Vec<&'static str> {
+        self.required_fields
+            .iter()
+            .copied()
+            .filter(|&field| !fields.contains_key(field))
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+pub enum InvalidComment {
+    #[error("comment is longer than {} characters", Comment::MAX_LEN)]
+    TooLong,
+    #[error("comment contains control characters")]
+    ControlCharacters,
+}
+
+/// Payment comment, checked against QIWI limits before sending.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Display, Serialize)]
+#[serde(transparent)]
+pub struct Comment(String);
+
+impl Comment {
+    /// In characters.
+    pub const MAX_LEN: usize = 255;
+
+    pub fn try_new(comment: impl Into<String>) -> Result<Self, InvalidComment> {
+        let comment = comment.into();
+        if comment.chars().count() > Self::MAX_LEN {
+            return Err(InvalidComment::TooLong);
+        }
+        if comment.chars().any(char::is_control) {
+            return Err(InvalidComment::ControlCharacters);
+        }
+
+        Ok(Self(comment))
+    }
+
+    /// Drops control characters and truncates to [`Comment::MAX_LEN`] instead of failing.
+    pub fn new_lossy(comment: &str) -> Self {
+        Self(
+            comment
+                .chars()
+                .filter(|c| !c.is_control())
+                .take(Self::MAX_LEN)
+                .collect(),
+        )
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::str::FromStr for Comment {
+    type Err = InvalidComment;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_new(s)
+    }
+}
+
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("{0:?} is not a valid UIN")]
+pub struct InvalidUin(pub String);
+
+/// Unique accrual identifier (УИН) of a tax, fine or duty, with its check digit verified.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Display)]
+pub struct Uin(String);
+
+impl Uin {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
+
+/// Check digit of UIN: weighted sum modulo 11, retried with weights shifted by 2 if it yields 10.
+fn uin_check_digit(digits: &[u32]) -> u32 {
+    for shift in [0, 2] {
+        let sum = digits
+            .iter()
+            .enumerate()
+            .map(|(i, digit)| digit * ((i as u32 + shift) % 10 + 1))
+            .sum::<u32>();
+        if sum % 11 < 10 {
+            return sum % 11;
+        }
+    }
+
+    0
+}
+
+impl std::str::FromStr for Uin {
+    type Err = InvalidUin;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s
+            .chars()
+            .map(|c| c.to_digit(10))
+            .collect::<Option<Vec<_>>>();
+        match digits {
+            Some(digits)
+                if matches!(digits.len(), 20 | 25)
+                    && uin_check_digit(&digits[..digits.len() - 1]) == digits[digits.len() - 1] =>
+            {
+                Ok(Self(s.to_string()))
+            }
+            _ => Err(InvalidUin(s.to_string())),
+        }
+    }
+}
+
+/// Requisites of a tax or fine payment to [`ProviderId::GOVERNMENT`].
+#[derive(Clone, Debug)]
+pub struct GovernmentPayment {
+    pub bik: String,
+    /// Recipient treasury account.
+    pub account: String,
+    /// Recipient name.
+    pub recipient: String,
+    pub uin: Uin,
+    pub purpose: String,
+}
+
+impl GovernmentPayment {
+    /// Payment fields as expected by [`ProviderId::GOVERNMENT`].
+    pub fn fields(&self) -> HashMap<String, String> {
+        [
+            ("account", self.account.clone()),
+            ("extra_to_bik", self.bik.clone()),
+            ("name", self.recipient.clone()),
+            ("UIN", self.uin.as_str().to_string()),
+            ("payment_purpose", self.purpose.clone()),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect()
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct CommissionRange {
+    #[serde(deserialize_with = "lenient_decimal")]
     pub bound: BigDecimal,
+    #[serde(deserialize_with = "lenient_decimal")]
     pub rate: BigDecimal,
+    #[serde(deserialize_with = "lenient_decimal")]
     pub min: BigDecimal,
+    #[serde(deserialize_with = "lenient_decimal")]
     pub max: BigDecimal,
+    #[serde(deserialize_with = "lenient_decimal")]
     pub fixed: BigDecimal,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct CommissionLimit {
-    pub currency: u16,
+    #[serde(with = "numeric_currency")]
+    pub currency: CurrencyCode,
+    #[serde(deserialize_with = "lenient_decimal")]
     pub min: BigDecimal,
+    #[serde(deserialize_with = "lenient_decimal")]
     pub max: BigDecimal,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct CommissionInfo {
+    /// Sorted by `bound` in ascending order.
+    #[serde(deserialize_with = "sorted_ranges")]
     pub ranges: Vec<CommissionRange>,
     pub limits: Vec<CommissionLimit>,
 }
 
+/// QIWI does not guarantee the order of commission ranges.
+fn sorted_ranges<'de, D>(deserializer: D) -> Result<Vec<CommissionRange>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut ranges = Vec::<CommissionRange>::deserialize(deserializer)?;
+    ranges.sort_by(|a, b| a.bound.cmp(&b.bound));
+    Ok(ranges)
+}
+
+impl CommissionInfo {
+    /// Computes commission for `amount` locally, without calling `onlineCommission`.
+    ///
+    /// Like QIWI, rounds half-up to kopecks.
+    pub fn commission_for(&self, amount: &BigDecimal) -> BigDecimal {
+        let Some(range) = self
+            .ranges
+            .iter()
+            .rev()
+            .find(|range| range.bound <= *amount)
+        else {
+            return BigDecimal::zero();
+        };
+
+        let mut commission = (amount * &range.rate).with_scale_round(2, RoundingMode::HalfUp);
+        if commission < range.min {
+            commission = range.min.clone();
+        }
+        // Zero upper bound means the commission is not capped
+        if !range.max.is_zero() && commission > range.max {
+            commission = range.max.clone();
+        }
+
+        (commission + &range.fixed).with_scale_round(2, RoundingMode::HalfUp)
+    }
+
+    /// Checks whether provider accepts transfer of `amount` in `currency`.
+    pub fn within_limits(&self, amount: &BigDecimal, currency: penny::Currency) -> bool {
+        self.limits.iter().any(|limit| {
+            limit.currency == currency
+                && *amount >= limit.min
+                && (limit.max.is_zero() || *amount <= limit.max)
+        })
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct CommissionInfoWrapper {
@@ -235,17 +1385,154 @@ pub(crate) struct CommissionInfoWrapper {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct CommissionQuoteData {
-    pub amount: BigDecimal,
+pub(crate) struct CommissionQuote {
+    pub qw_commission: Money,
 }
 
+/// Conversion terms including QIWI's spread and commission.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct CommissionQuote {
-    pub qw_commission: CommissionQuoteData,
+#[non_exhaustive]
+pub struct ConversionQuote {
+    /// Charged from the source account.
+    #[serde(rename = "withdrawSum")]
+    pub withdraw: Money,
+    /// Credited to the destination account.
+    #[serde(rename = "enrollmentSum")]
+    pub enrollment: Money,
+    #[serde(rename = "qwCommission")]
+    pub commission: Money,
+    /// Units of destination currency per unit of source currency actually applied.
+    #[serde(
+        rename = "withdrawToEnrollmentRate",
+        deserialize_with = "lenient_decimal"
+    )]
+    pub rate: BigDecimal,
+}
+
+/// Input form of a provider, describing `fields` expected by `Client::pay`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct ProviderForm {
+    pub elements: Vec<FormElement>,
+}
+
+impl ProviderForm {
+    /// Elements that are input fields, as opposed to e.g. dependent sub-forms.
+    pub fn fields(&self) -> impl Iterator<Item = &FormElement> {
+        self.elements
+            .iter()
+            .filter(|element| element.element_type == "field")
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct FormElement {
+    /// `field` for inputs.
+    #[serde(rename = "type")]
+    pub element_type: String,
+    /// Key in payment `fields`.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Prefilled or fixed value.
+    #[serde(default)]
+    pub value: Option<Value>,
+    #[serde(default)]
+    pub validator: Option<FormValidator>,
+    #[serde(default)]
+    pub view: Option<FormView>,
+}
+
+impl FormElement {
+    /// Regular expression the value must match, if any.
+    pub fn pattern(&self) -> Option<&str> {
+        self.validator
+            .as_ref()?
+            .predicate
+            .as_ref()?
+            .pattern
+            .as_deref()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct FormValidator {
+    #[serde(rename = "type")]
+    pub validator_type: String,
+    /// Shown to user when validation fails.
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub predicate: Option<FormPredicate>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct FormPredicate {
+    /// `regex` for pattern checks.
+    #[serde(rename = "type")]
+    pub predicate_type: String,
+    #[serde(default)]
+    pub pattern: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct FormView {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub prompt: Option<String>,
+    #[serde(default)]
+    pub widget: Option<FormWidget>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct FormWidget {
+    /// E.g. `text` or `list`.
+    #[serde(rename = "type")]
+    pub widget_type: String,
+    #[serde(default)]
+    pub keyboard: Option<String>,
+    /// Input mask, for formatting as user types.
+    #[serde(default)]
+    pub mask: Option<String>,
+    /// Options of a `list` widget.
+    #[serde(default)]
+    pub choices: Vec<FormChoice>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct FormChoice {
+    pub title: String,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ProviderSearchData {
+    pub providers: Vec<ProviderData>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ProviderFormWrapper {
+    pub content: ProviderForm,
 }
 
 #[derive(Clone, Debug)]
+#[non_exhaustive]
 pub enum TransferDirection {
     Qiwi {
         to_phone: PhoneNumber,
@@ -254,17 +1541,42 @@ pub enum TransferDirection {
     Cellular {
         carrier: u64,
         to_phone: PhoneNumber,
+        to_currency: penny::Currency,
+    },
+    /// Transfer to arbitrary bank account by its requisites.
+    Bank {
+        bik: String,
+        account: String,
+        recipient: String,
+        kpp: Option<String>,
+        urgent: bool,
+        to_service_id: Option<String>,
     },
 }
 
+impl TransferDirection {
+    /// Top-up of mobile phone billed in rubles.
+    pub fn cellular(carrier: u64, to_phone: PhoneNumber) -> Self {
+        Self::Cellular {
+            carrier,
+            to_phone,
+            to_currency: penny::Currency::RUB,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct TransferState {
     pub code: String,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
 pub struct TransferTransactionData {
     pub id: String,
     pub state: TransferState,
@@ -272,6 +1584,479 @@ pub struct TransferTransactionData {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[non_exhaustive]
 pub struct TransferData {
     pub transaction: TransferTransactionData,
+    #[serde(skip)]
+    pub(crate) id: u64,
+    #[serde(skip)]
+    pub(crate) duplicate: bool,
+}
+
+/// Result of `Client::transfer` and other payments.
+#[derive(Clone, Debug)]
+pub enum TransferOutcome {
+    Completed(TransferData),
+    /// Transfer is held until confirmed with `Client::confirm_transfer`.
+    ConfirmationRequired {
+        /// Payment id the transfer was made with.
+        id: u64,
+        txn_id: String,
+        /// Same as [`TransferData::is_duplicate`].
+        duplicate: bool,
+    },
+}
+
+impl From<TransferData> for TransferOutcome {
+    fn from(data: TransferData) -> Self {
+        if data.requires_confirmation() {
+            Self::ConfirmationRequired {
+                id: data.id,
+                txn_id: data.transaction.id,
+                duplicate: data.duplicate,
+            }
+        } else {
+            Self::Completed(data)
+        }
+    }
+}
+
+/// Formats as `Transfer #123 [Accepted]`.
+impl fmt::Display for TransferData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Transfer #{} [{}]",
+            self.transaction.id, self.transaction.state.code
+        )
+    }
+}
+
+impl TransferData {
+    /// State code of transactions held until confirmed with a code sent by SMS.
+    pub const AWAITING_CONFIRMATION: &'static str = "AwaitingSMSConfirmation";
+    /// State code of transactions accepted for processing.
+    pub const ACCEPTED: &'static str = "Accepted";
+
+    pub fn requires_confirmation(&self) -> bool {
+        self.transaction.state.code == Self::AWAITING_CONFIRMATION
+    }
+
+    /// Payment id the payment was made with, pass it again to retry without paying twice.
+    ///
+    /// Set by `Client`, zero if the request was executed directly.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Whether this is a replay of a payment already made with the same id rather than a new payment.
+    ///
+    /// Replays are detected with the client's `PaymentStore`, or by looking up payments with
+    /// caller-supplied id in the last week of history, since QIWI returns the original transaction
+    /// for a repeated id. Replays found in history are in [`TransferData::ACCEPTED`] state.
+    pub fn is_duplicate(&self) -> bool {
+        self.duplicate
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+pub struct BillCustomer {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+}
+
+/// Invoice to be paid by another user.
+#[derive(Clone, Debug)]
+pub struct BillRequest {
+    /// Unique id chosen by the merchant.
+    pub bill_id: String,
+    pub amount: BigDecimal,
+    pub currency: penny::Currency,
+    pub comment: Option<String>,
+    pub expiration_date_time: DateTime<Utc>,
+    pub customer: Option<BillCustomer>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
+pub struct BillAmount {
+    #[serde(deserialize_with = "lenient_decimal")]
+    pub value: BigDecimal,
+    #[serde(with = "alpha_currency")]
+    pub currency: penny::Currency,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+#[non_exhaustive]
+pub enum BillStatus {
+    Waiting,
+    Paid,
+    Rejected,
+    Expired,
+    Unknown(String),
+}
+
+impl BillStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Waiting => "WAITING",
+            Self::Paid => "PAID",
+            Self::Rejected => "REJECTED",
+            Self::Expired => "EXPIRED",
+            Self::Unknown(v) => v,
+        }
+    }
+}
+
+impl From<String> for BillStatus {
+    fn from(v: String) -> Self {
+        match v.as_str() {
+            "WAITING" => Self::Waiting,
+            "PAID" => Self::Paid,
+            "REJECTED" => Self::Rejected,
+            "EXPIRED" => Self::Expired,
+            _ => Self::Unknown(v),
+        }
+    }
+}
+
+impl From<BillStatus> for String {
+    fn from(v: BillStatus) -> Self {
+        v.as_str().to_string()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
+pub struct BillStatusData {
+    pub value: BillStatus,
+    #[serde(deserialize_with = "lenient_datetime")]
+    pub changed_date_time: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "strict", serde(deny_unknown_fields))]
+#[non_exhaustive]
+pub struct Bill {
+    pub site_id: String,
+    pub bill_id: String,
+    pub amount: BillAmount,
+    pub status: BillStatusData,
+    #[serde(default)]
+    pub customer: Option<BillCustomer>,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(deserialize_with = "lenient_datetime")]
+    pub creation_date_time: DateTime<Utc>,
+    #[serde(deserialize_with = "lenient_datetime")]
+    pub expiration_date_time: DateTime<Utc>,
+    pub pay_url: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// `commission` section of `sinap/providers/{id}/form` response.
+    fn commission(ranges: Value) -> CommissionInfo {
+        serde_json::from_value::<CommissionInfoWrapper>(json!({
+            "commission": {
+                "ranges": ranges,
+                "limits": [{ "currency": 643, "min": 1, "max": 15000 }]
+            }
+        }))
+        .unwrap()
+        .commission
+    }
+
+    fn decimal(s: &str) -> BigDecimal {
+        s.parse().unwrap()
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Numeric(#[serde(with = "numeric_currency")] CurrencyCode);
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Alpha(#[serde(with = "alpha_currency")] penny::Currency);
+
+    #[test]
+    fn numeric_currency_round_trip() {
+        for input in [json!(643), json!("643"), json!("RUB"), json!("rub")] {
+            let currency = serde_json::from_value::<Numeric>(input).unwrap();
+            assert_eq!(currency.0, penny::Currency::RUB);
+            assert_eq!(serde_json::to_value(&currency).unwrap(), json!(643));
+        }
+
+        let kzt = serde_json::from_value::<Numeric>(json!(398)).unwrap();
+        assert_eq!(kzt.0, penny::Currency::KZT);
+    }
+
+    #[test]
+    fn numeric_currency_unknown() {
+        let currency = serde_json::from_value::<Numeric>(json!(999)).unwrap();
+        assert_eq!(currency.0, CurrencyCode::Unknown("999".into()));
+        assert_eq!(serde_json::to_value(&currency).unwrap(), json!(999));
+
+        let currency = serde_json::from_value::<Numeric>(json!("XYZ")).unwrap();
+        assert_eq!(currency.0, CurrencyCode::Unknown("XYZ".into()));
+        assert_eq!(serde_json::to_value(&currency).unwrap(), json!("XYZ"));
+    }
+
+    #[test]
+    fn alpha_currency_round_trip() {
+        for input in ["USD", "usd", "840"] {
+            let currency = serde_json::from_value::<Alpha>(json!(input)).unwrap();
+            assert_eq!(currency, Alpha(penny::Currency::USD));
+            assert_eq!(serde_json::to_value(&currency).unwrap(), json!("USD"));
+        }
+
+        assert!(serde_json::from_value::<Alpha>(json!("XYZ")).is_err());
+        assert!(serde_json::from_value::<Alpha>(json!(840)).is_err());
+    }
+
+    #[test]
+    fn ids_are_plain_numbers() {
+        let person = PersonId::new(79161234567);
+        assert_eq!(serde_json::to_value(person).unwrap(), json!(79161234567u64));
+        assert_eq!(
+            serde_json::from_value::<PersonId>(json!(79161234567u64)).unwrap(),
+            person
+        );
+
+        assert_eq!(serde_json::to_string(&ProviderId::QIWI).unwrap(), "99");
+        assert_eq!(
+            serde_json::from_str::<ProviderId>("28004").unwrap(),
+            ProviderId::GOVERNMENT
+        );
+        assert!(serde_json::from_str::<ProviderId>(r#"{"id":99}"#).is_err());
+    }
+
+    #[test]
+    fn commission_fixed_fee() {
+        let info = commission(json!([
+            { "bound": 0, "rate": 0, "min": 0, "max": 0, "fixed": 50 }
+        ]));
+
+        assert_eq!(info.commission_for(&decimal("1000")), decimal("50.00"));
+        assert_eq!(info.commission_for(&decimal("0.01")), decimal("50.00"));
+    }
+
+    #[test]
+    fn commission_percentage_clamped() {
+        let info = commission(json!([
+            { "bound": 5000, "rate": 0.01, "min": 50, "max": 100, "fixed": 0 },
+            { "bound": 0, "rate": 0, "min": 0, "max": 0, "fixed": 0 }
+        ]));
+
+        assert_eq!(info.commission_for(&decimal("1000")), decimal("0.00"));
+        assert_eq!(info.commission_for(&decimal("5000")), decimal("50.00"));
+        assert_eq!(info.commission_for(&decimal("7000")), decimal("70.00"));
+        assert_eq!(info.commission_for(&decimal("20000")), decimal("100.00"));
+    }
+
+    #[test]
+    fn commission_rounds_half_up() {
+        let info = commission(json!([
+            { "bound": 0, "rate": "0.02", "min": 0, "max": 0, "fixed": "0.5" }
+        ]));
+
+        // 100.25 * 0.02 = 2.005
+        assert_eq!(info.commission_for(&decimal("100.25")), decimal("2.51"));
+        // 100.24 * 0.02 = 2.0048
+        assert_eq!(info.commission_for(&decimal("100.24")), decimal("2.50"));
+    }
+
+    #[test]
+    fn commission_ranges_shuffled() {
+        let ranges = [
+            json!({ "bound": 0, "rate": 0, "min": 0, "max": 0, "fixed": 10 }),
+            json!({ "bound": 1000, "rate": 0.02, "min": 30, "max": 0, "fixed": 0 }),
+            json!({ "bound": 5000, "rate": 0.01, "min": 0, "max": 200, "fixed": 0 }),
+        ];
+        let sorted = commission(json!(ranges));
+        let shuffled = commission(json!([ranges[2], ranges[0], ranges[1]]));
+
+        assert_eq!(
+            shuffled
+                .ranges
+                .iter()
+                .map(|range| range.bound.clone())
+                .collect::<Vec<_>>(),
+            [decimal("0"), decimal("1000"), decimal("5000")]
+        );
+        for amount in ["500", "1000", "2500", "5000", "30000"] {
+            assert_eq!(
+                shuffled.commission_for(&decimal(amount)),
+                sorted.commission_for(&decimal(amount)),
+                "amount {amount}"
+            );
+        }
+    }
+
+    /// `sinap/providers/{id}/form` and `onlineCommission` answers for the same provider.
+    fn assert_matches_online_commission(form: &str, online_commission: &str) {
+        let info = serde_json::from_str::<CommissionInfoWrapper>(form)
+            .unwrap()
+            .commission;
+        for quote in serde_json::from_str::<Vec<Value>>(online_commission).unwrap() {
+            let amount = serde_json::from_value::<Money>(quote["enrollmentSum"].clone()).unwrap();
+            let quote = serde_json::from_value::<CommissionQuote>(quote).unwrap();
+            assert_eq!(
+                info.commission_for(&amount.amount),
+                quote.qw_commission.amount,
+                "amount {}",
+                amount.amount
+            );
+        }
+    }
+
+    #[test]
+    fn commission_matches_online_commission() {
+        assert_matches_online_commission(
+            include_str!("../tests/fixtures/commission/99_form.json"),
+            include_str!("../tests/fixtures/commission/99_online_commission.json"),
+        );
+        assert_matches_online_commission(
+            include_str!("../tests/fixtures/commission/1963_form.json"),
+            include_str!("../tests/fixtures/commission/1963_online_commission.json"),
+        );
+    }
+
+    #[test]
+    fn uin_valid() {
+        for uin in [
+            "18810177171234567893",
+            "1881017717123456789012340",
+            // First pass yields 10, check digit comes from weights shifted by 2.
+            "32210300010000000015",
+            // Both passes yield 10, check digit is 0.
+            "18810177170000001060",
+        ] {
+            assert_eq!(uin.parse::<Uin>().unwrap().as_str(), uin);
+        }
+    }
+
+    #[test]
+    fn uin_invalid() {
+        for uin in [
+            // Flipped digit.
+            "18810177171234567993",
+            "1881017717123456789",
+            "1881017717123456789a",
+            "",
+        ] {
+            assert!(uin.parse::<Uin>().is_err(), "{uin}");
+        }
+    }
+
+    #[test]
+    fn parse_amount_valid() {
+        assert_eq!(parse_amount("10.50").unwrap(), decimal("10.5"));
+        assert_eq!(parse_amount("100").unwrap(), decimal("100"));
+    }
+
+    #[test]
+    fn parse_amount_invalid() {
+        for (input, error) in [
+            ("1e10", InvalidAmount::Malformed("1e10".into())),
+            ("-1", InvalidAmount::Malformed("-1".into())),
+            (" 10 ", InvalidAmount::Malformed(" 10 ".into())),
+            ("0", InvalidAmount::NotPositive),
+            ("100.999", InvalidAmount::TooPrecise),
+        ] {
+            assert_eq!(parse_amount(input), Err(error), "{input:?}");
+        }
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Timestamp(#[serde(deserialize_with = "lenient_datetime")] DateTime<Utc>);
+
+    #[derive(Debug, Deserialize)]
+    struct OptionalTimestamp(
+        #[serde(default, deserialize_with = "lenient_datetime_opt")] Option<DateTime<Utc>>,
+    );
+
+    #[test]
+    fn lenient_datetime_formats() {
+        let expected = Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap();
+        for input in [
+            json!("2024-03-01T12:00:00+03:00"),
+            json!("2024-03-01T09:00:00Z"),
+            json!(1_709_283_600_000_i64),
+            // Moscow local time, UTC+3.
+            json!("2024-03-01 12:00:00"),
+        ] {
+            let Timestamp(dt) = serde_json::from_value(input.clone()).unwrap();
+            assert_eq!(dt, expected, "{input}");
+
+            let OptionalTimestamp(dt) = serde_json::from_value(input.clone()).unwrap();
+            assert_eq!(dt, Some(expected), "{input}");
+        }
+
+        assert!(serde_json::from_value::<Timestamp>(json!("01.03.2024")).is_err());
+        let OptionalTimestamp(dt) = serde_json::from_value(Value::Null).unwrap();
+        assert_eq!(dt, None);
+    }
+
+    #[test]
+    fn lenient_decimal_float_is_exact() {
+        let range = serde_json::from_value::<CommissionRange>(json!({
+            "bound": 0, "rate": 0.1, "min": "0", "max": 0, "fixed": 50
+        }))
+        .unwrap();
+        let expected = <BigDecimal as std::str::FromStr>::from_str("0.1").unwrap();
+        assert_eq!(range.rate, expected);
+        assert_eq!(range.rate.to_string(), "0.1");
+        assert_eq!(range.fixed, decimal("50"));
+    }
+
+    #[test]
+    fn identification_details() {
+        let identification = serde_json::from_value::<Identification>(json!({
+            "id": 79001234567_u64,
+            "type": "VERIFIED",
+            "birthDate": "1990-01-01",
+            "firstName": "Иван",
+            "middleName": "Иванович",
+            "lastName": "Иванов",
+            "passport": "4400111222",
+            "inn": null,
+            "snils": null,
+            "oms": null,
+            "bank": "QIWI",
+            "checks": ["PASSPORT", "BIOMETRY"]
+        }))
+        .unwrap();
+        assert_eq!(identification.status(), IdentificationStatus::Partial);
+        assert_eq!(identification.bank, Some(BankAlias::Qiwi));
+        assert_eq!(
+            identification.checks.unwrap(),
+            [
+                IdentificationCheck::Passport,
+                IdentificationCheck::Unknown("BIOMETRY".into())
+            ]
+        );
+
+        let anonymous = serde_json::from_value::<Identification>(json!({
+            "id": 79001234567_u64,
+            "type": "ANONYMOUS"
+        }))
+        .unwrap();
+        assert_eq!(anonymous.status(), IdentificationStatus::NotVerified);
+        assert_eq!(anonymous.bank, None);
+        assert!(anonymous.checks.is_none());
+    }
 }
@@ -0,0 +1,53 @@
+use std::{num::NonZeroU32, sync::Mutex};
+use tokio::time::{sleep, Duration, Instant};
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket limiting the rate of requests.
+///
+/// Allows bursts of up to `requests_per_second` requests.
+#[derive(Debug)]
+pub struct RateLimiter {
+    rate: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: NonZeroU32) -> Self {
+        let rate = f64::from(requests_per_second.get());
+        Self {
+            rate,
+            bucket: Mutex::new(Bucket {
+                tokens: rate,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until the next request may be sent.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                bucket.tokens = (bucket.tokens
+                    + now.duration_since(bucket.updated).as_secs_f64() * self.rate)
+                    .min(self.rate);
+                bucket.updated = now;
+
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate)
+            };
+
+            sleep(wait).await;
+        }
+    }
+}
@@ -0,0 +1,958 @@
+use crate::models::*;
+use bigdecimal::{BigDecimal, RoundingMode};
+use chrono::prelude::*;
+use http::{header, Method};
+use penny::Currency;
+use phonenumber::PhoneNumber;
+use serde::{
+    de::{DeserializeOwned, Error as _},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    convert::TryFrom,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+};
+
+/// Error reported by QIWI in response body.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, thiserror::Error)]
+#[serde(rename_all = "camelCase")]
+#[error("qiwi error: {code}{}", .message.as_ref().map(|m| format!(" ({m})")).unwrap_or_default())]
+pub struct QiwiApiError {
+    #[serde(rename = "errorCode")]
+    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Recognizes error envelope used by a particular QIWI host.
+pub type ErrorEnvelope = fn(&Value) -> Option<QiwiApiError>;
+
+/// `{"errorCode": "...", "description": "..."}` returned by edge and bill APIs.
+///
+/// Numeric codes are ignored, successful payloads such as payment history entries carry their own.
+pub fn error_code_envelope(value: &Value) -> Option<QiwiApiError> {
+    if !value.get("errorCode")?.is_string() {
+        return None;
+    }
+
+    numeric_error_code_envelope(value)
+}
+
+/// Same as [`error_code_envelope`], but also treats non-zero numeric `errorCode` as an error.
+///
+/// Opt in with [`ApiRequest::with_error_envelopes`] for endpoints that report errors this way.
+pub fn numeric_error_code_envelope(value: &Value) -> Option<QiwiApiError> {
+    // Successful payloads may carry `errorCode: 0` too
+    let code = match value.get("errorCode")? {
+        Value::String(code) => code.clone(),
+        Value::Number(code) if code.as_u64() != Some(0) => code.to_string(),
+        _ => return None,
+    };
+    let message = ["userMessage", "description", "message"]
+        .into_iter()
+        .find_map(|key| value.get(key)?.as_str());
+
+    Some(QiwiApiError {
+        code,
+        message: message.map(ToString::to_string),
+    })
+}
+
+/// `{"code": {"value": "..."}, "message": "..."}` returned by qiwi.com actions.
+pub fn code_value_envelope(value: &Value) -> Option<QiwiApiError> {
+    let code = value.get("code")?.get("value")?;
+    let code = match code {
+        Value::String(code) => code.clone(),
+        Value::Number(code) => code.to_string(),
+        _ => return None,
+    };
+
+    Some(QiwiApiError {
+        code,
+        message: value
+            .get("message")
+            .and_then(Value::as_str)
+            .map(ToString::to_string),
+    })
+}
+
+/// Envelopes recognized when deserializing [`Rsp`] and by requests that do not override them.
+pub const DEFAULT_ERROR_ENVELOPES: &[ErrorEnvelope] = &[error_code_envelope, code_value_envelope];
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum Rsp<T> {
+    Error(QiwiApiError),
+    OK(T),
+}
+
+impl<T: DeserializeOwned> Rsp<T> {
+    /// Parses response, recognizing errors with `envelopes`.
+    pub fn from_value(value: Value, envelopes: &[ErrorEnvelope]) -> serde_json::Result<Self> {
+        if let Some(error) = envelopes.iter().find_map(|envelope| envelope(&value)) {
+            return Ok(Self::Error(error));
+        }
+
+        T::deserialize(value).map(Self::OK)
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Rsp<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Self::from_value(Value::deserialize(deserializer)?, DEFAULT_ERROR_ENVELOPES)
+            .map_err(D::Error::custom)
+    }
+}
+
+impl<T> Rsp<T> {
+    pub fn into_result(self) -> anyhow::Result<T> {
+        match self {
+            Self::Error(error) => Err(error.into()),
+            Self::OK(v) => Ok(v),
+        }
+    }
+}
+
+fn parse_rsp<T: DeserializeOwned>(data: &[u8], envelopes: &[ErrorEnvelope]) -> anyhow::Result<T> {
+    Rsp::from_value(serde_json::from_slice(data)?, envelopes)?.into_result()
+}
+
+/// Joins API address and endpoint with exactly one slash between them.
+pub(crate) fn join_url(addr: &str, endpoint: &str) -> String {
+    format!(
+        "{}/{}",
+        addr.trim_end_matches('/'),
+        endpoint.trim_start_matches('/')
+    )
+}
+
+fn json_body(body: &impl Serialize) -> Vec<u8> {
+    serde_json::to_vec(body).expect("request bodies are always serializable")
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PaymentSum {
+    pub amount: BigDecimal,
+    pub currency: QiwiCurrency,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PaymentMethod {
+    #[serde(rename = "type")]
+    pub method_type: &'static str,
+    pub account_id: QiwiCurrency,
+}
+
+impl PaymentMethod {
+    /// Wallet account in `currency`.
+    fn account(currency: Currency) -> Self {
+        Self {
+            method_type: "Account",
+            account_id: QiwiCurrency(currency),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PurchaseTotals {
+    pub total: PaymentSum,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CommissionQuoteRequest {
+    pub account: String,
+    pub payment_method: PaymentMethod,
+    pub purchase_totals: PurchaseTotals,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TransferPaymentRequest {
+    pub id: String,
+    pub sum: PaymentSum,
+    pub payment_method: PaymentMethod,
+    pub fields: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<Comment>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CreateBillRequest {
+    pub amount: BillAmount,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    pub expiration_date_time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub customer: Option<BillCustomer>,
+}
+
+/// Generates unique payment id. Pass it explicitly to make retries idempotent.
+///
+/// Current time in milliseconds is followed by 20 bits of a sequence starting at a random point,
+/// so ids are unique within the process and unlikely to collide between processes.
+pub fn new_payment_id() -> u64 {
+    static SEED: OnceLock<u64> = OnceLock::new();
+    static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+    let seed = *SEED.get_or_init(|| RandomState::new().build_hasher().finish());
+    let sequence = seed.wrapping_add(SEQUENCE.fetch_add(1, Ordering::Relaxed)) & 0xf_ffff;
+    u64::try_from(Utc::now().timestamp_millis()).unwrap() << 20 | sequence
+}
+
+/// Provider, sum currency and payment fields of a transfer in `direction`.
+pub(crate) fn transfer_target(
+    direction: TransferDirection,
+) -> (ProviderId, Currency, HashMap<String, String>) {
+    match direction {
+        TransferDirection::Qiwi {
+            to_phone,
+            to_currency,
+        } => (
+            ProviderId::QIWI,
+            to_currency,
+            HashMap::from([("account".to_string(), QiwiUser(to_phone).to_string())]),
+        ),
+        TransferDirection::Cellular {
+            carrier,
+            to_phone,
+            to_currency,
+        } => (
+            ProviderId(carrier),
+            to_currency,
+            HashMap::from([("account".to_string(), QiwiUser(to_phone).to_string())]),
+        ),
+        TransferDirection::Bank {
+            bik,
+            account,
+            recipient,
+            kpp,
+            urgent,
+            to_service_id,
+        } => {
+            let mut fields = HashMap::from([
+                ("account".to_string(), account),
+                ("bik".to_string(), bik),
+                ("name".to_string(), recipient),
+                (
+                    "urgent".to_string(),
+                    if urgent { "1" } else { "0" }.to_string(),
+                ),
+            ]);
+            if let Some(kpp) = kpp {
+                fields.insert("kpp".to_string(), kpp);
+            }
+            if let Some(to_service_id) = to_service_id {
+                fields.insert("toServiceId".to_string(), to_service_id);
+            }
+
+            (ProviderId::OTHER_BANK, Currency::RUB, fields)
+        }
+    }
+}
+
+/// Description of a single API call, independent of the HTTP stack used to execute it.
+#[derive(Clone, Debug)]
+#[must_use = "requests do nothing unless executed"]
+pub struct ApiRequest<T> {
+    pub method: Method,
+    pub endpoint: String,
+    pub params: HashMap<&'static str, String>,
+    /// Serialized JSON.
+    pub body: Option<Vec<u8>>,
+    /// Recognize errors in response body, see [`ApiRequest::with_error_envelopes`].
+    pub error_envelopes: &'static [ErrorEnvelope],
+    pub(crate) parse: fn(&[u8], &[ErrorEnvelope]) -> anyhow::Result<T>,
+}
+
+impl<T> ApiRequest<T> {
+    /// Builds an HTTP request against API located at `addr`.
+    pub fn to_http(
+        &self,
+        addr: &str,
+        bearer: Option<&str>,
+    ) -> http::Result<http::Request<Vec<u8>>> {
+        let mut uri = join_url(addr, &self.endpoint);
+        if !self.params.is_empty() {
+            uri.push('?');
+            uri.push_str(
+                &form_urlencoded::Serializer::new(String::new())
+                    .extend_pairs(&self.params)
+                    .finish(),
+            );
+        }
+
+        let mut req = http::Request::builder()
+            .method(self.method.clone())
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(bearer) = bearer {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {bearer}"));
+        }
+
+        req.body(self.body.clone().unwrap_or_default())
+    }
+
+    /// Replaces error envelopes recognized in response, e.g. for a host with its own error format.
+    pub fn with_error_envelopes(mut self, envelopes: &'static [ErrorEnvelope]) -> Self {
+        self.error_envelopes = envelopes;
+        self
+    }
+
+    /// Parses response body returned by the API.
+    pub fn parse_response(&self, data: &[u8]) -> anyhow::Result<T> {
+        (self.parse)(data, self.error_envelopes)
+    }
+}
+
+impl<T: DeserializeOwned> ApiRequest<T> {
+    /// Call to an arbitrary endpoint not modeled by this crate.
+    pub fn raw(
+        method: Method,
+        endpoint: String,
+        params: HashMap<&'static str, String>,
+        body: Option<Value>,
+    ) -> Self {
+        Self {
+            method,
+            endpoint,
+            params,
+            body: body.as_ref().map(json_body),
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: parse_rsp,
+        }
+    }
+}
+
+impl ApiRequest<ProfileInfo> {
+    pub fn profile_info() -> Self {
+        Self::profile_info_with(ProfileSections::ALL)
+    }
+
+    pub fn profile_info_with(sections: ProfileSections) -> Self {
+        Self {
+            method: Method::GET,
+            endpoint: "person-profile/v1/profile/current".into(),
+            params: HashMap::from([
+                ("authInfoEnabled", sections.auth_info.to_string()),
+                ("contractInfoEnabled", sections.contract_info.to_string()),
+                ("userInfoEnabled", sections.user_info.to_string()),
+            ]),
+            body: None,
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: parse_rsp,
+        }
+    }
+}
+
+impl ApiRequest<Identification> {
+    pub fn identification(user: &PhoneNumber) -> Self {
+        Self {
+            method: Method::GET,
+            endpoint: format!(
+                "identification/v1/persons/{}/identification",
+                QiwiUser(user.clone())
+            ),
+            params: Default::default(),
+            body: None,
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: parse_rsp,
+        }
+    }
+}
+
+impl ApiRequest<Vec<PaymentSource>> {
+    pub fn payment_sources(user: &PhoneNumber) -> Self {
+        Self {
+            method: Method::GET,
+            endpoint: format!(
+                "funding-sources/v2/persons/{}/accounts",
+                QiwiUser(user.clone())
+            ),
+            params: Default::default(),
+            body: None,
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: |data, envelopes| Ok(parse_rsp::<AccountsData<_>>(data, envelopes)?.accounts),
+        }
+    }
+}
+
+impl ApiRequest<Vec<AccountBalance>> {
+    pub fn accounts(user: &PhoneNumber) -> Self {
+        Self {
+            method: Method::GET,
+            endpoint: format!(
+                "funding-sources/v2/persons/{}/accounts",
+                QiwiUser(user.clone())
+            ),
+            params: Default::default(),
+            body: None,
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: |data, envelopes| Ok(parse_rsp::<AccountsData<_>>(data, envelopes)?.accounts),
+        }
+    }
+}
+
+impl ApiRequest<Nickname> {
+    pub fn nickname(user: &PhoneNumber) -> Self {
+        Self {
+            method: Method::GET,
+            endpoint: format!(
+                "qw-nicknames/v1/persons/{}/nickname",
+                QiwiUser(user.clone())
+            ),
+            params: Default::default(),
+            body: None,
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: parse_rsp,
+        }
+    }
+}
+
+impl ApiRequest<()> {
+    pub fn set_nickname(user: &PhoneNumber, nickname: &str) -> Self {
+        Self {
+            method: Method::POST,
+            endpoint: format!(
+                "qw-nicknames/v1/persons/{}/nickname",
+                QiwiUser(user.clone())
+            ),
+            params: Default::default(),
+            body: Some(json_body(&json!({ "nickname": nickname }))),
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: |data, envelopes| parse_rsp::<Value>(data, envelopes).map(drop),
+        }
+    }
+}
+
+impl ApiRequest<PaymentHistoryData> {
+    /// Single page of payment history, optionally continuing from `cursor`.
+    pub fn payment_history(
+        user: &PhoneNumber,
+        query: &HistoryQuery,
+        cursor: Option<HistoryCursor>,
+    ) -> Self {
+        let mut params = HashMap::new();
+        params.insert(
+            "rows",
+            query
+                .rows
+                .unwrap_or(HistoryQuery::MAX_ROWS)
+                .clamp(1, HistoryQuery::MAX_ROWS)
+                .to_string(),
+        );
+        if let Some(start_date) = &query.start_date {
+            params.insert("startDate", qiwi_datetime(start_date));
+        }
+        if let Some(end_date) = &query.end_date {
+            params.insert("endDate", qiwi_datetime(end_date));
+        }
+        const SOURCE_KEYS: [&str; FundingSource::ALL.len()] = [
+            "sources[0]",
+            "sources[1]",
+            "sources[2]",
+            "sources[3]",
+            "sources[4]",
+        ];
+        for (key, source) in SOURCE_KEYS.into_iter().zip(&query.sources) {
+            params.insert(key, source.as_str().to_string());
+        }
+        if query.operation != HistoryOperation::All {
+            params.insert("operation", query.operation.as_str().to_string());
+        }
+        if let Some(cursor) = cursor {
+            params.insert("nextTxnDate", cursor.next_txn_date);
+            params.insert("nextTxnId", cursor.next_txn_id.to_string());
+        }
+
+        Self {
+            method: Method::GET,
+            endpoint: format!(
+                "payment-history/v2/persons/{}/payments",
+                QiwiUser(user.clone())
+            ),
+            params,
+            body: None,
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: parse_rsp,
+        }
+    }
+}
+
+impl ApiRequest<PaymentHistoryEntry> {
+    pub fn transaction(txn_id: u64) -> Self {
+        Self {
+            method: Method::GET,
+            endpoint: format!("payment-history/v2/transactions/{}", txn_id),
+            params: Default::default(),
+            body: None,
+            // Failed payments carry numeric `errorCode` of their own, not an API error
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: parse_rsp,
+        }
+    }
+}
+
+impl ApiRequest<Vec<u8>> {
+    /// PDF of the bank document (payment order) for transaction `txn_id`.
+    pub fn bank_document(txn_id: u64, payment_type: PaymentType) -> Self {
+        Self {
+            method: Method::GET,
+            endpoint: format!(
+                "payment-history/v1/transactions/{}/bankDocument/file",
+                txn_id
+            ),
+            params: HashMap::from([
+                ("type", payment_type.as_str().to_string()),
+                ("format", "PDF".to_string()),
+            ]),
+            body: None,
+            // Body is a file, not JSON
+            error_envelopes: &[],
+            parse: |data, _| Ok(data.to_vec()),
+        }
+    }
+}
+
+impl ApiRequest<CommissionInfo> {
+    pub fn commission_info(provider: ProviderId) -> Self {
+        Self {
+            method: Method::GET,
+            endpoint: format!("sinap/providers/{}/form", provider),
+            params: Default::default(),
+            body: None,
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: |data, envelopes| {
+                Ok(parse_rsp::<CommissionInfoWrapper>(data, envelopes)?.commission)
+            },
+        }
+    }
+
+    /// Commission terms for paying to `account`, for providers whose fee depends on destination.
+    pub fn commission_info_for_account(provider: ProviderId, account: String) -> Self {
+        Self {
+            params: HashMap::from([("account", account)]),
+            ..Self::commission_info(provider)
+        }
+    }
+}
+
+impl ApiRequest<ProviderForm> {
+    pub fn provider_form(provider: ProviderId) -> Self {
+        Self {
+            method: Method::GET,
+            endpoint: format!("sinap/providers/{}/form", provider),
+            params: Default::default(),
+            body: None,
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: |data, envelopes| Ok(parse_rsp::<ProviderFormWrapper>(data, envelopes)?.content),
+        }
+    }
+}
+
+impl ApiRequest<Vec<ProviderData>> {
+    /// Free-text search in provider catalog.
+    pub fn providers(query: &str) -> Self {
+        Self {
+            method: Method::GET,
+            endpoint: "sinap/api/v2/providers/search".into(),
+            params: HashMap::from([("query", query.to_string())]),
+            body: None,
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: |data, envelopes| Ok(parse_rsp::<ProviderSearchData>(data, envelopes)?.providers),
+        }
+    }
+}
+
+impl ApiRequest<Money> {
+    pub fn commission_quote(
+        provider: ProviderId,
+        account: PhoneNumber,
+        amount: BigDecimal,
+        currency: Currency,
+    ) -> Self {
+        Self::online_commission(
+            provider,
+            QiwiUser(account).to_string(),
+            amount,
+            currency,
+            currency,
+        )
+    }
+
+    /// Commission for paying `amount` in `currency` to `account`, charged from wallet account in `from_currency`.
+    pub fn online_commission(
+        provider: ProviderId,
+        account: String,
+        amount: BigDecimal,
+        currency: Currency,
+        from_currency: Currency,
+    ) -> Self {
+        Self {
+            method: Method::POST,
+            endpoint: format!("sinap/providers/{}/onlineCommission", provider),
+            params: Default::default(),
+            body: Some(json_body(&CommissionQuoteRequest {
+                account,
+                payment_method: PaymentMethod::account(from_currency),
+                purchase_totals: PurchaseTotals {
+                    total: PaymentSum {
+                        amount,
+                        currency: QiwiCurrency(currency),
+                    },
+                },
+            })),
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: |data, envelopes| {
+                Ok(parse_rsp::<CommissionQuote>(data, envelopes)?.qw_commission)
+            },
+        }
+    }
+}
+
+impl ApiRequest<ConversionQuote> {
+    /// Terms of converting into `amount` of `to` currency from the wallet account in `from` currency.
+    pub fn conversion_quote(
+        user: &PhoneNumber,
+        from: Currency,
+        to: Currency,
+        amount: BigDecimal,
+    ) -> Self {
+        let ApiRequest {
+            method,
+            endpoint,
+            params,
+            body,
+            error_envelopes,
+            ..
+        } = ApiRequest::online_commission(
+            ProviderId::CONVERSION,
+            QiwiUser(user.clone()).to_string(),
+            amount,
+            to,
+            from,
+        );
+        Self {
+            method,
+            endpoint,
+            params,
+            body,
+            error_envelopes,
+            parse: parse_rsp,
+        }
+    }
+}
+
+impl ApiRequest<TransferData> {
+    /// Generic payment to `provider` with arbitrary `fields`.
+    pub fn pay(
+        provider: ProviderId,
+        sum: BigDecimal,
+        currency: Currency,
+        from_currency: Currency,
+        fields: HashMap<String, String>,
+        comment: Option<Comment>,
+        id: Option<u64>,
+    ) -> Self {
+        let body = TransferPaymentRequest {
+            id: id.unwrap_or_else(new_payment_id).to_string(),
+            sum: PaymentSum {
+                amount: sum,
+                currency: QiwiCurrency(currency),
+            },
+            payment_method: PaymentMethod::account(from_currency),
+            fields,
+            comment,
+        };
+
+        Self {
+            method: Method::POST,
+            endpoint: format!("sinap/api/v2/terms/{}/payments", provider),
+            params: Default::default(),
+            body: Some(json_body(&body)),
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: parse_rsp,
+        }
+    }
+
+    pub fn transfer(
+        id: Option<u64>,
+        amount: BigDecimal,
+        from_currency: Currency,
+        direction: TransferDirection,
+        comment: Comment,
+    ) -> Self {
+        let (provider, sum_currency, fields) = transfer_target(direction);
+
+        Self::pay(
+            provider,
+            amount,
+            sum_currency,
+            from_currency,
+            fields,
+            Some(comment),
+            id,
+        )
+    }
+
+    /// Confirms transfer held with [`TransferData::AWAITING_CONFIRMATION`] state using `code` from SMS.
+    pub fn confirm_transfer(txn_id: &str, code: &str) -> Self {
+        Self {
+            method: Method::POST,
+            endpoint: format!("sinap/api/v2/payments/{}/confirm", txn_id),
+            params: Default::default(),
+            body: Some(json_body(&json!({ "code": code }))),
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: parse_rsp,
+        }
+    }
+}
+
+impl ApiRequest<Bill> {
+    /// Creates P2P bill, to be sent to `https://api.qiwi.com` with the bill API secret key.
+    ///
+    /// Amount is rounded half-up to 2 decimal places, `BillClient::create_bill` rejects such amounts instead.
+    pub fn create_bill(bill: BillRequest) -> Self {
+        let body = CreateBillRequest {
+            amount: BillAmount {
+                value: bill.amount.with_scale_round(2, RoundingMode::HalfUp),
+                currency: bill.currency,
+            },
+            comment: bill.comment,
+            expiration_date_time: qiwi_datetime(&bill.expiration_date_time),
+            customer: bill.customer,
+        };
+
+        Self {
+            method: Method::PUT,
+            endpoint: format!("partner/bill/v1/bills/{}", bill.bill_id),
+            params: Default::default(),
+            body: Some(json_body(&body)),
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: parse_rsp,
+        }
+    }
+
+    pub fn bill_status(bill_id: &str) -> Self {
+        Self {
+            method: Method::GET,
+            endpoint: format!("partner/bill/v1/bills/{}", bill_id),
+            params: Default::default(),
+            body: None,
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: parse_rsp,
+        }
+    }
+
+    pub fn reject_bill(bill_id: &str) -> Self {
+        Self {
+            method: Method::POST,
+            endpoint: format!("partner/bill/v1/bills/{}/reject", bill_id),
+            params: Default::default(),
+            body: None,
+            error_envelopes: DEFAULT_ERROR_ENVELOPES,
+            parse: parse_rsp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: Value) -> Rsp<Value> {
+        Rsp::from_value(value, DEFAULT_ERROR_ENVELOPES).unwrap()
+    }
+
+    #[test]
+    fn zero_error_code_is_success() {
+        let body = json!({ "errorCode": 0, "txnId": 1 });
+        match Rsp::<Value>::from_value(body.clone(), &[numeric_error_code_envelope]).unwrap() {
+            Rsp::OK(value) => assert_eq!(value, body),
+            Rsp::Error(error) => panic!("unexpected error {error}"),
+        }
+    }
+
+    #[test]
+    fn string_error_code_is_error() {
+        let rsp = parse(json!({
+            "errorCode": "validation.error",
+            "description": "Invalid amount"
+        }));
+        match rsp {
+            Rsp::Error(error) => assert_eq!(
+                error,
+                QiwiApiError {
+                    code: "validation.error".into(),
+                    message: Some("Invalid amount".into()),
+                }
+            ),
+            Rsp::OK(value) => panic!("unexpected success {value}"),
+        }
+    }
+
+    #[test]
+    fn numeric_error_code_is_success_by_default() {
+        let body = json!({ "txnId": 1, "errorCode": 3 });
+        match parse(body.clone()) {
+            Rsp::OK(value) => assert_eq!(value, body),
+            Rsp::Error(error) => panic!("unexpected error {error}"),
+        }
+    }
+
+    #[test]
+    fn numeric_error_code_is_error() {
+        let rsp = Rsp::<Value>::from_value(
+            json!({ "errorCode": 3, "userMessage": "Insufficient funds" }),
+            &[numeric_error_code_envelope],
+        )
+        .unwrap();
+        match rsp {
+            Rsp::Error(error) => {
+                assert_eq!(error.code, "3");
+                assert_eq!(error.message.as_deref(), Some("Insufficient funds"));
+            }
+            Rsp::OK(value) => panic!("unexpected success {value}"),
+        }
+    }
+
+    #[test]
+    fn code_value_error() {
+        let rsp = parse(json!({ "code": { "value": 7 }, "message": "Unknown carrier" }));
+        assert!(matches!(rsp, Rsp::Error(QiwiApiError { code, .. }) if code == "7"));
+    }
+
+    #[test]
+    fn into_result_error() {
+        let error = parse_rsp::<Value>(
+            br#"{"errorCode":"internal.error"}"#,
+            DEFAULT_ERROR_ENVELOPES,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error
+                .downcast_ref::<QiwiApiError>()
+                .map(|e| e.code.as_str()),
+            Some("internal.error")
+        );
+    }
+
+    #[test]
+    fn request_error_envelopes() {
+        let body = br#"{"errorCode":3}"#;
+        let req = ApiRequest::<Value>::raw(Method::GET, "test".into(), Default::default(), None);
+        assert_eq!(req.parse_response(body).unwrap(), json!({ "errorCode": 3 }));
+        assert!(req
+            .with_error_envelopes(&[numeric_error_code_envelope])
+            .parse_response(body)
+            .is_err());
+    }
+
+    fn rub(amount: &str) -> PaymentSum {
+        PaymentSum {
+            amount: amount.parse().unwrap(),
+            currency: QiwiCurrency(Currency::RUB),
+        }
+    }
+
+    #[test]
+    fn payment_sum_wire_format() {
+        assert_eq!(
+            serde_json::to_value(rub("10.5")).unwrap(),
+            json!({ "amount": "10.5", "currency": "643" })
+        );
+        assert_eq!(
+            serde_json::to_value(PaymentMethod::account(Currency::USD)).unwrap(),
+            json!({ "type": "Account", "accountId": "840" })
+        );
+    }
+
+    #[test]
+    fn commission_quote_wire_format() {
+        let body = CommissionQuoteRequest {
+            account: "79161112233".into(),
+            payment_method: PaymentMethod::account(Currency::RUB),
+            purchase_totals: PurchaseTotals { total: rub("100") },
+        };
+        assert_eq!(
+            serde_json::to_value(body).unwrap(),
+            json!({
+                "account": "79161112233",
+                "paymentMethod": { "type": "Account", "accountId": "643" },
+                "purchaseTotals": { "total": { "amount": "100", "currency": "643" } }
+            })
+        );
+    }
+
+    #[test]
+    fn transfer_payment_wire_format() {
+        let mut body = TransferPaymentRequest {
+            id: "42".into(),
+            sum: rub("10"),
+            payment_method: PaymentMethod::account(Currency::RUB),
+            fields: HashMap::from([("account".to_string(), "79161112233".to_string())]),
+            comment: Some("thanks".parse().unwrap()),
+        };
+        assert_eq!(
+            serde_json::to_value(&body).unwrap(),
+            json!({
+                "id": "42",
+                "sum": { "amount": "10", "currency": "643" },
+                "paymentMethod": { "type": "Account", "accountId": "643" },
+                "fields": { "account": "79161112233" },
+                "comment": "thanks"
+            })
+        );
+
+        body.comment = None;
+        assert!(serde_json::to_value(&body)
+            .unwrap()
+            .get("comment")
+            .is_none());
+    }
+
+    #[test]
+    fn payment_ids_are_unique() {
+        let ids = (0..10_000)
+            .map(|_| new_payment_id())
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(ids.len(), 10_000);
+    }
+
+    #[test]
+    fn create_bill_wire_format() {
+        let req = ApiRequest::create_bill(BillRequest {
+            bill_id: "bill-1".into(),
+            amount: "10.005".parse().unwrap(),
+            currency: Currency::KZT,
+            comment: None,
+            expiration_date_time: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            customer: Some(BillCustomer {
+                email: Some("user@example.com".into()),
+                ..Default::default()
+            }),
+        });
+        assert_eq!(req.endpoint, "partner/bill/v1/bills/bill-1");
+        assert_eq!(
+            serde_json::from_slice::<Value>(&req.body.unwrap()).unwrap(),
+            json!({
+                "amount": { "value": "10.01", "currency": "KZT" },
+                "expirationDateTime": "2024-01-01T03:00:00+03:00",
+                "customer": { "email": "user@example.com" }
+            })
+        );
+    }
+}
@@ -1,52 +1,249 @@
-use anyhow::format_err;
+use crate::request::*;
+use anyhow::{format_err, Context};
 use headers::*;
-use http::Method;
+use http::{HeaderMap, Method, StatusCode};
 use reqwest_ext::*;
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use serde_json::Value;
 use std::{
     collections::HashMap,
-    fmt::{Debug, Display},
+    fmt::{self, Debug, Display, Formatter},
     future::Future,
     pin::Pin,
-    sync::Arc,
+    sync::{Arc, RwLock},
+    time::Duration,
 };
 use tracing::*;
+// `std::time::Instant::now` panics in the browser
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase", untagged)]
-pub enum Rsp<T> {
-    Error {
-        #[serde(rename = "errorCode")]
-        error: String,
-    },
-    OK(T),
-}
-
-impl<T> Rsp<T> {
-    pub fn into_result(self) -> anyhow::Result<T> {
-        match self {
-            Self::Error { error } => Err(format_err!("qiwi error: {error}")),
-            Self::OK(v) => Ok(v),
-        }
-    }
+#[cfg(not(target_arch = "wasm32"))]
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+/// Futures are not `Send` in the browser.
+#[cfg(target_arch = "wasm32")]
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + 'static>>;
+
+/// `Send + Sync`, except in the browser where everything runs on a single thread.
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MaybeSendSync: Send + Sync {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send + Sync + ?Sized> MaybeSendSync for T {}
+/// `Send + Sync`, except in the browser where everything runs on a single thread.
+#[cfg(target_arch = "wasm32")]
+pub trait MaybeSendSync {}
+#[cfg(target_arch = "wasm32")]
+impl<T: ?Sized> MaybeSendSync for T {}
+
+/// Non-successful HTTP status returned by the API.
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("Received error {status} with data: {body}")]
+pub struct HttpError {
+    pub status: StatusCode,
+    /// E.g. `Retry-After` of rate-limited requests.
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct TransportResponse {
+    pub headers: HeaderMap,
+    /// Raw, not necessarily UTF-8, e.g. a PDF document.
+    pub body: Vec<u8>,
 }
 
-pub trait Transport: Debug + Send + Sync + 'static {
+pub trait Transport: Debug + MaybeSendSync + 'static {
     fn call(
         &self,
         endpoint: String,
         method: Method,
         params: &HashMap<&str, String>,
-        body: Option<&Value>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + 'static>>;
+        body: Option<&[u8]>,
+    ) -> BoxFuture<anyhow::Result<String>>;
+
+    /// Same as [`Transport::call`], but also returns response headers and keeps body as bytes.
+    ///
+    /// Transports that do not override this return empty headers and can't receive binary bodies.
+    fn call_with_headers(
+        &self,
+        endpoint: String,
+        method: Method,
+        params: &HashMap<&str, String>,
+        body: Option<&[u8]>,
+    ) -> BoxFuture<anyhow::Result<TransportResponse>> {
+        let c = self.call(endpoint, method, params, body);
+        Box::pin(async move {
+            Ok(TransportResponse {
+                headers: HeaderMap::new(),
+                body: c.await?.into_bytes(),
+            })
+        })
+    }
+
+    /// Replaces token used for subsequent requests.
+    fn set_bearer(&self, _bearer: String) -> anyhow::Result<()> {
+        Err(format_err!("transport does not support changing token"))
+    }
+}
+
+/// Hooks invoked around each HTTP request, e.g. to collect metrics.
+pub trait Observer: MaybeSendSync + 'static {
+    fn on_request(&self, _endpoint: &str, _method: &Method) {}
+
+    /// `status` is `None` if no response was received.
+    fn on_response(&self, _endpoint: &str, _status: Option<StatusCode>, _elapsed: Duration) {}
+}
+
+async fn send_observed<E>(
+    send: impl Future<Output = Result<reqwest::Response, E>>,
+    observer: Option<Arc<dyn Observer>>,
+    endpoint: String,
+    method: Method,
+) -> Result<reqwest::Response, E> {
+    let observer = match observer {
+        Some(observer) => observer,
+        None => return send.await,
+    };
+
+    observer.on_request(&endpoint, &method);
+    let started = Instant::now();
+    let rsp = send.await;
+    observer.on_response(
+        &endpoint,
+        rsp.as_ref().ok().map(|rsp| rsp.status()),
+        started.elapsed(),
+    );
+
+    rsp
 }
 
-#[derive(Debug)]
 pub struct RemoteCaller {
     pub http_client: reqwest::Client,
     pub addr: String,
-    pub bearer: Option<String>,
+    /// Can be replaced at runtime with [`Transport::set_bearer`].
+    pub bearer: RwLock<Option<String>>,
+    /// Mask accounts, card numbers, tokens and phone numbers in trace logs.
+    pub redact_logs: bool,
+    pub observer: Option<Arc<dyn Observer>>,
+}
+
+impl RemoteCaller {
+    pub fn builder() -> RemoteCallerBuilder {
+        RemoteCallerBuilder::default()
+    }
+}
+
+#[derive(Clone)]
+#[must_use = "builders do nothing unless built"]
+pub struct RemoteCallerBuilder {
+    addr: String,
+    bearer: Option<String>,
+    user_agent: String,
+    redact_logs: bool,
+    observer: Option<Arc<dyn Observer>>,
+    #[cfg(feature = "dangerous-insecure")]
+    accept_invalid_certs: bool,
+}
+
+impl Default for RemoteCallerBuilder {
+    fn default() -> Self {
+        Self {
+            addr: "https://edge.qiwi.com".into(),
+            bearer: None,
+            user_agent: concat!("qiwi-rs/", env!("CARGO_PKG_VERSION")).into(),
+            redact_logs: true,
+            observer: None,
+            #[cfg(feature = "dangerous-insecure")]
+            accept_invalid_certs: false,
+        }
+    }
+}
+
+impl Debug for RemoteCallerBuilder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteCallerBuilder")
+            .field("addr", &self.addr)
+            .field("bearer", &self.bearer.as_ref().map(|_| "***"))
+            .field("user_agent", &self.user_agent)
+            .field("redact_logs", &self.redact_logs)
+            .field("observer", &self.observer.is_some())
+            .finish()
+    }
+}
+
+impl RemoteCallerBuilder {
+    /// API base URL, `https://edge.qiwi.com` by default.
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.addr = addr.into();
+        self
+    }
+
+    pub fn bearer(mut self, bearer: impl Into<String>) -> Self {
+        self.bearer = Some(bearer.into());
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Enabled by default.
+    pub fn redact_logs(mut self, redact_logs: bool) -> Self {
+        self.redact_logs = redact_logs;
+        self
+    }
+
+    pub fn observer(mut self, observer: impl Observer) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Accept invalid TLS certificates, for tests against local mocks only.
+    #[cfg(feature = "dangerous-insecure")]
+    pub fn danger_accept_invalid_certs(mut self, accept_invalid_certs: bool) -> Self {
+        self.accept_invalid_certs = accept_invalid_certs;
+        self
+    }
+
+    /// Fails if address is not a valid URL.
+    pub fn build(self) -> anyhow::Result<RemoteCaller> {
+        reqwest::Url::parse(&self.addr)
+            .with_context(|| format!("invalid API address {}", self.addr))?;
+
+        let http_client = reqwest::Client::builder().default_headers(HeaderMap::from_iter([(
+            http::header::USER_AGENT,
+            self.user_agent.parse::<http::HeaderValue>()?,
+        )]));
+        #[cfg(feature = "dangerous-insecure")]
+        let http_client = http_client.danger_accept_invalid_certs(self.accept_invalid_certs);
+        let http_client = http_client.build()?;
+
+        Ok(RemoteCaller {
+            http_client,
+            addr: self.addr.trim_end_matches('/').to_string(),
+            bearer: RwLock::new(self.bearer),
+            redact_logs: self.redact_logs,
+            observer: self.observer,
+        })
+    }
+}
+
+impl Debug for RemoteCaller {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteCaller")
+            .field("http_client", &self.http_client)
+            .field("addr", &self.addr)
+            .field(
+                "bearer",
+                &self.bearer.read().unwrap().as_ref().map(|_| "***"),
+            )
+            .field("redact_logs", &self.redact_logs)
+            .field("observer", &self.observer.is_some())
+            .finish()
+    }
 }
 
 impl Transport for RemoteCaller {
@@ -55,65 +252,342 @@ impl Transport for RemoteCaller {
         endpoint: String,
         method: Method,
         params: &HashMap<&str, String>,
-        body: Option<&Value>,
-    ) -> Pin<Box<dyn Future<Output = anyhow::Result<String>> + Send + 'static>> {
+        body: Option<&[u8]>,
+    ) -> BoxFuture<anyhow::Result<String>> {
+        let c = self.call_with_headers(endpoint, method, params, body);
+        Box::pin(async move { Ok(String::from_utf8(c.await?.body)?) })
+    }
+
+    fn call_with_headers(
+        &self,
+        endpoint: String,
+        method: Method,
+        params: &HashMap<&str, String>,
+        body: Option<&[u8]>,
+    ) -> BoxFuture<anyhow::Result<TransportResponse>> {
         let client = self.http_client.clone();
-        let uri = format!("{}/{}", self.addr, endpoint);
-        trace!(
-            "Sending request to endpoint {} with params: {:?}",
-            endpoint,
-            params
-        );
+        let uri = join_url(&self.addr, &endpoint);
+        log_request(self.redact_logs, &endpoint, params, body);
 
         let mut req = client
-            .request(method, uri)
+            .request(method.clone(), uri)
             .query(params)
             .typed_header(ContentType::json());
-        if let Some(bearer) = self.bearer.as_ref() {
+        if let Some(bearer) = self.bearer.read().unwrap().as_ref() {
             req = req.bearer_auth(bearer);
         }
 
         if let Some(body) = body {
-            req = req.json(body);
+            req = req.body(body.to_vec());
         }
 
+        let redact_logs = self.redact_logs;
+        let observer = self.observer.clone();
         Box::pin(async move {
-            let rsp = req.send().await?;
-            let err = rsp.error_for_status_ref().err();
+            read_response(
+                send_observed(req.send(), observer, endpoint, method).await?,
+                redact_logs,
+            )
+            .await
+        })
+    }
+
+    fn set_bearer(&self, bearer: String) -> anyhow::Result<()> {
+        *self.bearer.write().unwrap() = Some(bearer);
+        Ok(())
+    }
+}
+
+fn log_request(
+    redact_logs: bool,
+    endpoint: &str,
+    params: &HashMap<&str, String>,
+    body: Option<&[u8]>,
+) {
+    if !enabled!(Level::TRACE) {
+        return;
+    }
+
+    if redact_logs {
+        let params = params
+            .iter()
+            .map(|(&k, v)| (k, if is_sensitive(k) { "***" } else { v.as_str() }))
+            .collect::<HashMap<_, _>>();
+        let body = body.map(|body| match serde_json::from_slice::<Value>(body) {
+            Ok(mut body) => {
+                redact_value(&mut body);
+                body.to_string()
+            }
+            Err(_) => "<non-JSON body>".to_string(),
+        });
+        trace!(
+            "Sending request to endpoint {} with params: {:?}, body: {:?}",
+            redact_endpoint(endpoint),
+            params,
+            body
+        );
+    } else {
+        trace!(
+            "Sending request to endpoint {} with params: {:?}, body: {:?}",
+            endpoint,
+            params,
+            body.map(String::from_utf8_lossy)
+        );
+    }
+}
 
-            let data = rsp.text().await?;
+async fn read_response(
+    rsp: reqwest::Response,
+    redact_logs: bool,
+) -> anyhow::Result<TransportResponse> {
+    let status = rsp.status();
+    let headers = rsp.headers().clone();
 
-            trace!("Received HTTP response: {data}");
+    let data = rsp.bytes().await?.to_vec();
 
-            if let Some(err) = err {
-                return Err(format_err!("Received error {err} with data: {data}"));
+    if redact_logs && enabled!(Level::TRACE) {
+        let data = match serde_json::from_slice::<Value>(&data) {
+            Ok(mut value) => {
+                redact_value(&mut value);
+                value.to_string()
             }
+            Err(_) => "<non-JSON body>".to_string(),
+        };
+        trace!("Received HTTP response: {data}");
+    } else {
+        trace!("Received HTTP response: {}", String::from_utf8_lossy(&data));
+    }
+
+    if status.is_client_error() || status.is_server_error() {
+        return Err(HttpError {
+            status,
+            headers,
+            body: String::from_utf8_lossy(&data).into_owned(),
+        }
+        .into());
+    }
+
+    Ok(TransportResponse {
+        headers,
+        body: data,
+    })
+}
+
+/// Transport running requests through `reqwest-middleware` stack.
+#[cfg(feature = "middleware")]
+pub struct MiddlewareCaller {
+    pub http_client: reqwest_middleware::ClientWithMiddleware,
+    pub addr: String,
+    /// Can be replaced at runtime with [`Transport::set_bearer`].
+    pub bearer: RwLock<Option<String>>,
+    /// Mask accounts, card numbers, tokens and phone numbers in trace logs.
+    pub redact_logs: bool,
+    pub observer: Option<Arc<dyn Observer>>,
+}
+
+#[cfg(feature = "middleware")]
+impl Debug for MiddlewareCaller {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MiddlewareCaller")
+            .field("addr", &self.addr)
+            .field(
+                "bearer",
+                &self.bearer.read().unwrap().as_ref().map(|_| "***"),
+            )
+            .field("redact_logs", &self.redact_logs)
+            .field("observer", &self.observer.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "middleware")]
+impl Transport for MiddlewareCaller {
+    fn call(
+        &self,
+        endpoint: String,
+        method: Method,
+        params: &HashMap<&str, String>,
+        body: Option<&[u8]>,
+    ) -> BoxFuture<anyhow::Result<String>> {
+        let c = self.call_with_headers(endpoint, method, params, body);
+        Box::pin(async move { Ok(String::from_utf8(c.await?.body)?) })
+    }
+
+    fn call_with_headers(
+        &self,
+        endpoint: String,
+        method: Method,
+        params: &HashMap<&str, String>,
+        body: Option<&[u8]>,
+    ) -> BoxFuture<anyhow::Result<TransportResponse>> {
+        let uri = join_url(&self.addr, &endpoint);
+        log_request(self.redact_logs, &endpoint, params, body);
+
+        let mut req = self
+            .http_client
+            .request(method.clone(), uri)
+            .query(params)
+            .header(http::header::CONTENT_TYPE, "application/json");
+        if let Some(bearer) = self.bearer.read().unwrap().as_ref() {
+            req = req.bearer_auth(bearer);
+        }
+
+        if let Some(body) = body {
+            req = req.body(body.to_vec());
+        }
 
-            Ok(data)
+        let redact_logs = self.redact_logs;
+        let observer = self.observer.clone();
+        Box::pin(async move {
+            read_response(
+                send_observed(req.send(), observer, endpoint, method).await?,
+                redact_logs,
+            )
+            .await
         })
     }
+
+    fn set_bearer(&self, bearer: String) -> anyhow::Result<()> {
+        *self.bearer.write().unwrap() = Some(bearer);
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct CallerWrapper {
     pub transport: Arc<dyn Transport>,
+    /// Shared by all clones of the wrapper.
+    #[cfg(feature = "rate-limit")]
+    pub rate_limiter: Option<Arc<crate::RateLimiter>>,
 }
 
 impl CallerWrapper {
+    pub fn new(transport: impl Transport) -> Self {
+        Self {
+            transport: Arc::new(transport),
+            #[cfg(feature = "rate-limit")]
+            rate_limiter: None,
+        }
+    }
+
+    fn throttle(&self) -> BoxFuture<()> {
+        #[cfg(feature = "rate-limit")]
+        if let Some(rate_limiter) = self.rate_limiter.clone() {
+            return Box::pin(async move { rate_limiter.acquire().await });
+        }
+
+        Box::pin(async {})
+    }
+
     pub fn call<E, T>(
         &self,
         endpoint: E,
         method: Method,
         params: &HashMap<&str, String>,
-        body: Option<&Value>,
-    ) -> impl Future<Output = anyhow::Result<Rsp<T>>> + Send + 'static
+        body: Option<&[u8]>,
+    ) -> BoxFuture<anyhow::Result<Rsp<T>>>
     where
         E: Display,
         T: for<'de> Deserialize<'de> + Send + 'static,
     {
-        let c = self
-            .transport
-            .call(endpoint.to_string(), method, params, body);
-        async move { Ok(serde_json::from_str(&c.await?)?) }
+        let endpoint = endpoint.to_string();
+        let throttle = self.throttle();
+        let transport = self.transport.clone();
+        let params = params
+            .iter()
+            .map(|(&k, v)| (k.to_string(), v.clone()))
+            .collect::<HashMap<_, _>>();
+        let body = body.map(<[u8]>::to_vec);
+        Box::pin(async move {
+            // Transport may start the request as soon as it is called
+            throttle.await;
+            let params = params
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.clone()))
+                .collect::<HashMap<_, _>>();
+            let c = transport.call(endpoint.clone(), method, &params, body.as_deref());
+            async move { anyhow::Ok(serde_json::from_str(&c.await?)?) }
+                .await
+                .with_context(|| format!("while calling {}", redact_endpoint(&endpoint)))
+        })
     }
+
+    pub fn execute<T>(&self, req: ApiRequest<T>) -> BoxFuture<anyhow::Result<T>>
+    where
+        T: Send + 'static,
+    {
+        let c = self.execute_with_headers(req);
+        Box::pin(async move { Ok(c.await?.1) })
+    }
+
+    /// Same as [`CallerWrapper::execute`], but also returns response headers.
+    pub fn execute_with_headers<T>(
+        &self,
+        req: ApiRequest<T>,
+    ) -> BoxFuture<anyhow::Result<(HeaderMap, T)>>
+    where
+        T: Send + 'static,
+    {
+        let ApiRequest {
+            method,
+            endpoint,
+            params,
+            body,
+            error_envelopes,
+            parse,
+        } = req;
+        let throttle = self.throttle();
+        let transport = self.transport.clone();
+        Box::pin(async move {
+            // Transport may start the request as soon as it is called
+            throttle.await;
+            let c = transport.call_with_headers(endpoint.clone(), method, &params, body.as_deref());
+            async move {
+                let rsp = c.await?;
+                anyhow::Ok((rsp.headers, parse(&rsp.body, error_envelopes)?))
+            }
+            .await
+            .with_context(|| format!("while calling {}", redact_endpoint(&endpoint)))
+        })
+    }
+}
+
+const SENSITIVE_KEYS: &[&str] = &["account", "pan", "token", "phone", "personid"];
+
+fn is_sensitive(key: &str) -> bool {
+    SENSITIVE_KEYS
+        .iter()
+        .any(|sensitive| key.eq_ignore_ascii_case(sensitive))
+}
+
+fn redact_value(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map.iter_mut() {
+                if is_sensitive(k) {
+                    *v = Value::String("***".into());
+                } else {
+                    redact_value(v);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_value),
+        _ => {}
+    }
+}
+
+/// Masks wallet ids (phone numbers) in endpoint paths like `persons/79991234567/payments`, for logs
+/// and error contexts.
+fn redact_endpoint(endpoint: &str) -> String {
+    endpoint
+        .split('/')
+        .map(|segment| {
+            if segment.len() >= 10 && segment.bytes().all(|b| b.is_ascii_digit()) {
+                "***"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
 }
@@ -0,0 +1,679 @@
+#![cfg(feature = "client")]
+
+use bigdecimal::BigDecimal;
+use futures_util::TryStreamExt;
+use penny::Currency;
+use qiwi::*;
+use serde_json::{json, Value};
+use wiremock::{matchers::*, Mock, MockServer, ResponseTemplate};
+
+const WALLET: &str = "79161234567";
+const TOKEN: &str = "secret";
+
+fn client(server: &MockServer) -> Client {
+    Client::with_base_url(format!("+{WALLET}").parse().unwrap(), TOKEN, server.uri()).unwrap()
+}
+
+fn history_entry(txn_id: u64) -> Value {
+    json!({
+        "txnId": txn_id,
+        "personId": 79161234567u64,
+        "date": "2023-01-01T12:00:00+03:00",
+        "errorCode": 0,
+        "error": "",
+        "type": "OUT",
+        "status": "SUCCESS",
+        "statusText": "Success",
+        "trmTxnId": txn_id.to_string(),
+        "account": "+79161112233",
+        "sum": { "amount": 100, "currency": 643 },
+        "commission": { "amount": 0, "currency": 643 },
+        "total": { "amount": 100, "currency": 643 },
+        "provider": {
+            "id": 99,
+            "shortName": "QIWI Wallet",
+            "longName": "QIWI Wallet",
+            "logoUrl": "",
+            "description": "",
+            "keys": "",
+            "siteUrl": ""
+        },
+        "comment": "",
+        "currencyRate": 1,
+        "extras": {},
+        "chequeReady": true,
+        "bankDocumentAvailable": false,
+        "bankDocumentReady": false,
+        "repeatPaymentEnabled": true,
+        "favoritePaymentEnabled": true,
+        "regularPaymentEnabled": true
+    })
+}
+
+/// Outgoing history searched before retrying a payment with known id.
+async fn mount_out_history(server: &MockServer, data: Vec<Value>) {
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "/payment-history/v2/persons/{WALLET}/payments"
+        )))
+        .and(query_param("operation", "OUT"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": data,
+            "nextTxnId": null,
+            "nextTxnDate": null
+        })))
+        .expect(1)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn profile_info() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/person-profile/v1/profile/current"))
+        .and(query_param("authInfoEnabled", "true"))
+        .and(query_param("contractInfoEnabled", "true"))
+        .and(query_param("userInfoEnabled", "true"))
+        .and(bearer_token(TOKEN))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "authInfo": {
+                "personId": 79161234567u64,
+                "registrationDate": "2020-01-01T00:00:00+03:00",
+                "boundEmail": null,
+                "ip": "127.0.0.1",
+                "lastLoginDate": null,
+                "mobilePinInfo": {
+                    "mobilePinUsed": true,
+                    "lastMobilePinChange": "2020-01-01T00:00:00+03:00",
+                    "nextMobilePinChange": "2021-01-01T00:00:00+03:00"
+                },
+                "passInfo": {
+                    "passwordUsed": true,
+                    "lastPassChange": "2020-01-01T00:00:00+03:00",
+                    "nextPassChange": "2021-01-01T00:00:00+03:00"
+                },
+                "pinInfo": { "pinUsed": true }
+            }
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let profile = client(&server).profile_info().await.unwrap();
+    assert_eq!(profile.auth_info.unwrap().person_id.get(), 79161234567);
+    assert!(profile.contract_info.is_none());
+    assert!(profile.user_info.is_none());
+}
+
+#[tokio::test]
+async fn payment_history_pagination() {
+    let server = MockServer::start().await;
+    let endpoint = format!("/payment-history/v2/persons/{WALLET}/payments");
+    Mock::given(method("GET"))
+        .and(path(endpoint.as_str()))
+        .and(query_param("rows", "50"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [history_entry(3), history_entry(2)],
+            "nextTxnId": 1,
+            "nextTxnDate": "2023-01-01T10:00:00+03:00"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(endpoint.as_str()))
+        .and(query_param("nextTxnId", "1"))
+        .and(query_param("nextTxnDate", "2023-01-01T10:00:00+03:00"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [history_entry(1)],
+            "nextTxnId": null,
+            "nextTxnDate": null
+        })))
+        .with_priority(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let history = client(&server).payment_history_vec(0).await.unwrap();
+    assert_eq!(
+        history.iter().map(|entry| entry.txn_id).collect::<Vec<_>>(),
+        [3, 2, 1]
+    );
+    assert_eq!(history[0].sum.currency, Currency::RUB);
+}
+
+#[tokio::test]
+async fn transfer() {
+    let server = MockServer::start().await;
+    mount_out_history(&server, Vec::new()).await;
+    Mock::given(method("POST"))
+        .and(path("/sinap/api/v2/terms/99/payments"))
+        .and(bearer_token(TOKEN))
+        .and(body_partial_json(json!({
+            "id": "42",
+            "sum": { "currency": "643" },
+            "paymentMethod": { "type": "Account", "accountId": "643" },
+            "fields": { "account": "79161112233" },
+            "comment": "thanks"
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "42",
+            "transaction": {
+                "id": "11111",
+                "state": { "code": "Accepted" }
+            }
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let outcome = client(&server)
+        .transfer(
+            Some(42),
+            BigDecimal::from(10),
+            Currency::RUB,
+            TransferDirection::Qiwi {
+                to_phone: "+79161112233".parse().unwrap(),
+                to_currency: Currency::RUB,
+            },
+            "thanks".parse().unwrap(),
+        )
+        .await
+        .unwrap();
+    let data = match outcome {
+        TransferOutcome::Completed(data) => data,
+        other => panic!("unexpected outcome {other:?}"),
+    };
+    assert_eq!(data.id(), 42);
+    assert_eq!(data.transaction.id, "11111");
+    assert_eq!(data.transaction.state.code, "Accepted");
+    assert!(!data.is_duplicate());
+}
+
+#[tokio::test]
+async fn set_token() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(
+            "/identification/v1/persons/79161234567/identification",
+        ))
+        .and(bearer_token("rotated"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": 79161234567u64,
+            "type": "SIMPLE",
+            "birthDate": null,
+            "firstName": null,
+            "middleName": null,
+            "lastName": null,
+            "passport": null,
+            "inn": null,
+            "snils": null,
+            "oms": null
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server);
+    let clone = client.clone();
+    client.set_token("rotated").unwrap();
+    let identification = clone.identification().await.unwrap();
+    assert_eq!(identification.status(), IdentificationStatus::Partial);
+}
+
+#[tokio::test]
+async fn observer() {
+    #[derive(Clone, Default)]
+    struct Recorder(std::sync::Arc<std::sync::Mutex<Vec<(String, Option<http::StatusCode>)>>>);
+
+    impl Observer for Recorder {
+        fn on_response(
+            &self,
+            endpoint: &str,
+            status: Option<http::StatusCode>,
+            _elapsed: std::time::Duration,
+        ) {
+            self.0.lock().unwrap().push((endpoint.to_string(), status));
+        }
+    }
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+
+    let recorder = Recorder::default();
+    let transport = RemoteCaller::builder()
+        .addr(server.uri())
+        .observer(recorder.clone())
+        .build()
+        .unwrap();
+    let client = Client::with_transport(format!("+{WALLET}").parse().unwrap(), transport);
+    assert!(client.nickname().await.is_err());
+    assert_eq!(
+        *recorder.0.lock().unwrap(),
+        [(
+            "qw-nicknames/v1/persons/79161234567/nickname".to_string(),
+            Some(http::StatusCode::INTERNAL_SERVER_ERROR)
+        )]
+    );
+}
+
+#[tokio::test]
+async fn transfer_preview() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/sinap/providers/99/onlineCommission"))
+        .and(body_partial_json(json!({ "account": "79161112233" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "qwCommission": { "amount": 0.5, "currency": 643 }
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sinap/api/v2/terms/99/payments"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let preview = client(&server)
+        .transfer_preview(
+            Some(42),
+            BigDecimal::from(10),
+            Currency::RUB,
+            TransferDirection::Qiwi {
+                to_phone: "+79161112233".parse().unwrap(),
+                to_currency: Currency::RUB,
+            },
+            "thanks".parse().unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(preview.id, 42);
+    assert_eq!(
+        preview.commission.amount,
+        "0.5".parse::<BigDecimal>().unwrap()
+    );
+    assert_eq!(preview.request.endpoint, "sinap/api/v2/terms/99/payments");
+}
+
+#[tokio::test]
+async fn error_context_redacts_wallet() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).nickname().await.unwrap_err();
+    let message = format!("{error:#}");
+    assert!(
+        message.contains("while calling qw-nicknames/v1/persons/***/nickname"),
+        "{message}"
+    );
+    assert!(!message.contains(WALLET), "{message}");
+}
+
+#[tokio::test]
+async fn transfer_preview_validates_amount() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let error = client(&server)
+        .transfer_preview(
+            None,
+            "10.005".parse().unwrap(),
+            Currency::RUB,
+            TransferDirection::Qiwi {
+                to_phone: "+79161112233".parse().unwrap(),
+                to_currency: Currency::RUB,
+            },
+            Comment::default(),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<InvalidAmount>(),
+        Some(&InvalidAmount::TooPrecise)
+    );
+}
+
+#[tokio::test]
+async fn payment_history_overlapping_pages() {
+    fn entry(txn_id: u64, date: &str) -> Value {
+        let mut entry = history_entry(txn_id);
+        entry["date"] = json!(date);
+        entry
+    }
+
+    let server = MockServer::start().await;
+    let endpoint = format!("/payment-history/v2/persons/{WALLET}/payments");
+    Mock::given(method("GET"))
+        .and(path(endpoint.as_str()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [
+                entry(4, "2023-01-01T11:00:00+03:00"),
+                entry(5, "2023-01-01T12:00:00+03:00"),
+                entry(2, "2023-01-01T09:00:00+03:00"),
+                entry(3, "2023-01-01T10:00:00+03:00")
+            ],
+            "nextTxnId": 3,
+            "nextTxnDate": "2023-01-01T10:00:00+03:00"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(endpoint.as_str()))
+        .and(query_param("nextTxnId", "3"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [
+                entry(1, "2023-01-01T08:00:00+03:00"),
+                entry(3, "2023-01-01T10:00:00+03:00"),
+                entry(2, "2023-01-01T09:00:00+03:00"),
+                entry(1, "2023-01-01T08:00:00+03:00")
+            ],
+            "nextTxnId": null,
+            "nextTxnDate": null
+        })))
+        .with_priority(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let history = client(&server).payment_history_vec(0).await.unwrap();
+    assert_eq!(
+        history.iter().map(|entry| entry.txn_id).collect::<Vec<_>>(),
+        [5, 4, 3, 2, 1]
+    );
+}
+
+#[tokio::test]
+async fn transfer_duplicate_id() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/sinap/api/v2/terms/99/payments"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&server)
+        .await;
+    let mut entry = history_entry(11111);
+    entry["trmTxnId"] = json!("42");
+    mount_out_history(&server, vec![history_entry(22222), entry]).await;
+
+    let outcome = client(&server)
+        .transfer(
+            Some(42),
+            BigDecimal::from(10),
+            Currency::RUB,
+            TransferDirection::Qiwi {
+                to_phone: "+79161112233".parse().unwrap(),
+                to_currency: Currency::RUB,
+            },
+            Comment::default(),
+        )
+        .await
+        .unwrap();
+    let data = match outcome {
+        TransferOutcome::Completed(data) => data,
+        other => panic!("unexpected outcome {other:?}"),
+    };
+    assert!(data.is_duplicate());
+    assert_eq!(data.id(), 42);
+    assert_eq!(data.transaction.id, "11111");
+    assert_eq!(data.transaction.state.code, TransferData::ACCEPTED);
+}
+
+#[tokio::test]
+async fn response_headers() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(format!("/qw-nicknames/v1/persons/{WALLET}/nickname")))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("X-Request-Id", "abc")
+                .set_body_json(json!({
+                    "nickname": null,
+                    "canChange": true,
+                    "description": ""
+                })),
+        )
+        .mount(&server)
+        .await;
+
+    let (headers, nickname) = client(&server)
+        .execute_with_headers(ApiRequest::nickname(&format!("+{WALLET}").parse().unwrap()))
+        .await
+        .unwrap();
+    assert_eq!(headers["x-request-id"], "abc");
+    assert!(nickname.nickname.is_none());
+}
+
+#[tokio::test]
+async fn http_error_headers() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3"))
+        .mount(&server)
+        .await;
+
+    let error = client(&server).nickname().await.unwrap_err();
+    let error = error.downcast_ref::<HttpError>().unwrap();
+    assert_eq!(error.status, http::StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(error.headers["retry-after"], "3");
+}
+
+#[tokio::test]
+async fn bank_document() {
+    let pdf = b"%PDF-1.4\n\xff\xfe\x00binary".to_vec();
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(
+            "/payment-history/v1/transactions/11111/bankDocument/file",
+        ))
+        .and(query_param("type", "OUT"))
+        .and(query_param("format", "PDF"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(pdf.clone()))
+        .mount(&server)
+        .await;
+
+    let document = client(&server)
+        .bank_document(11111, PaymentType::Out)
+        .await
+        .unwrap();
+    assert_eq!(document, pdf);
+}
+
+#[tokio::test]
+async fn payment_history_oldest_first() {
+    let server = MockServer::start().await;
+    let endpoint = format!("/payment-history/v2/persons/{WALLET}/payments");
+    for (start_date, data) in [
+        ("2023-01-01T03:00:00+03:00", [2, 1]),
+        // Boundary payment 2 is repeated
+        ("2023-01-02T03:00:00+03:00", [3, 2]),
+    ] {
+        Mock::given(method("GET"))
+            .and(path(endpoint.as_str()))
+            .and(query_param("startDate", start_date))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "data": data.map(history_entry),
+                "nextTxnId": null,
+                "nextTxnDate": null
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+
+    let client = client(&server);
+    let history = client
+        .payment_history_filtered(HistoryQuery {
+            start_date: Some("2023-01-01T00:00:00Z".parse().unwrap()),
+            end_date: Some("2023-01-02T12:00:00Z".parse().unwrap()),
+            oldest_first: true,
+            ..Default::default()
+        })
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(
+        history.iter().map(|entry| entry.txn_id).collect::<Vec<_>>(),
+        [1, 2, 3]
+    );
+
+    assert!(client
+        .payment_history_filtered(HistoryQuery {
+            oldest_first: true,
+            ..Default::default()
+        })
+        .try_collect::<Vec<_>>()
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn confirm_transfer_updates_store() {
+    let server = MockServer::start().await;
+    mount_out_history(&server, Vec::new()).await;
+    Mock::given(method("POST"))
+        .and(path("/sinap/api/v2/terms/99/payments"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "id": "42",
+            "transaction": {
+                "id": "11111",
+                "state": { "code": "AwaitingSMSConfirmation" }
+            }
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sinap/api/v2/payments/11111/confirm"))
+        .and(body_json(json!({ "code": "1234" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "transaction": {
+                "id": "11111",
+                "state": { "code": "Accepted" }
+            }
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server).with_payment_store(MemoryPaymentStore::default());
+    let transfer = || {
+        client.transfer(
+            Some(42),
+            BigDecimal::from(10),
+            Currency::RUB,
+            TransferDirection::Qiwi {
+                to_phone: "+79161112233".parse().unwrap(),
+                to_currency: Currency::RUB,
+            },
+            "thanks".parse().unwrap(),
+        )
+    };
+
+    let (id, txn_id) = match transfer().await.unwrap() {
+        TransferOutcome::ConfirmationRequired {
+            id,
+            txn_id,
+            duplicate,
+        } => {
+            assert!(!duplicate);
+            (id, txn_id)
+        }
+        other => panic!("unexpected outcome {other:?}"),
+    };
+    assert_eq!(id, 42);
+    let data = client.confirm_transfer(id, &txn_id, "1234").await.unwrap();
+    assert_eq!(data.id(), 42);
+
+    // Replay is answered from the store
+    match transfer().await.unwrap() {
+        TransferOutcome::Completed(data) => {
+            assert!(data.is_duplicate());
+            assert_eq!(data.transaction.state.code, "Accepted");
+        }
+        other => panic!("unexpected outcome {other:?}"),
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn payment_history_cancelled_after_page() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "/payment-history/v2/persons/{WALLET}/payments"
+        )))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [history_entry(3), history_entry(2)],
+            "nextTxnId": 1,
+            "nextTxnDate": "2023-01-01T10:00:00+03:00"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let cancel = tokio_util::sync::CancellationToken::new();
+    cancel.cancel();
+    let history = client(&server)
+        .payment_history_filtered_until(Default::default(), cancel)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+    assert_eq!(
+        history.iter().map(|entry| entry.txn_id).collect::<Vec<_>>(),
+        [3, 2]
+    );
+}
+
+#[tokio::test]
+async fn ping() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/person-profile/v1/profile/current"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&server)
+        .await;
+    assert!(matches!(
+        client(&server).ping().await,
+        Err(PingError::TokenInvalid)
+    ));
+
+    server.reset().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&server)
+        .await;
+    assert!(matches!(
+        client(&server).ping().await,
+        Err(PingError::Unavailable(_))
+    ));
+}
+
+#[test]
+fn topup_qr_region() {
+    let phone = format!("+{WALLET}");
+    let ru = Client::new(phone.parse().unwrap(), TOKEN).unwrap();
+    assert_eq!(
+        ru.topup_qr().unwrap(),
+        format!("https://qiwi.com/payment/form/99?extra%5B%27account%27%5D={WALLET}&blocked%5B0%5D=account")
+    );
+
+    let kz = Client::with_region(phone.parse().unwrap(), TOKEN, Region::Kz).unwrap();
+    assert!(kz
+        .topup_qr()
+        .unwrap()
+        .starts_with("https://qiwi.kz/payment/form/99?"));
+}